@@ -1,103 +1,463 @@
 use proc_macro::TokenStream;
-use proc_macro2::TokenStream as TokenStream2;
+use proc_macro2::{Ident, Span, TokenStream as TokenStream2};
 use quote::{format_ident, quote, ToTokens};
 
-use crate::{Abstract, Rule, Segment};
+use crate::{Abstract, Flags, IntoCapture, Rule, ScanInto, Segment};
 
 pub(crate) fn emit(abs: Abstract) -> TokenStream {
     abs.to_token_stream().into()
 }
 
+pub(crate) fn emit_into(scan_into: ScanInto) -> TokenStream {
+    scan_into.to_token_stream().into()
+}
+
+/// Emits a constant declared with `#[format_string]`, along with a macro of
+/// the same name that passes its value to a scanning macro. The macro is
+/// declared in a module of its own, so that it can be imported by path
+/// without conflicting with the constant.
+pub(crate) fn emit_format_const(item: syn::ItemConst) -> TokenStream {
+    let name = &item.ident;
+    let value = &item.expr;
+    let module = format_ident!("__rescan_format_{}", name);
+    // Macros can only be used by path within their own crate.
+    let vis = match &item.vis {
+        syn::Visibility::Inherited => quote!(),
+        syn::Visibility::Public(_) => quote!(pub(crate)),
+        vis => quote!(#vis),
+    };
+    // The constant may only be used through its macro.
+    quote! {
+        #[allow(dead_code)]
+        #item
+
+        #[doc(hidden)]
+        #[allow(non_snake_case)]
+        mod #module {
+            macro_rules! #name {
+                ([$($mac:tt)*], [$($before:tt)*], [$($after:tt)*], [$($path:tt)*]) => {
+                    $($mac)*!($($before)* #value $($after)*)
+                };
+            }
+            pub(crate) use #name;
+        }
+        #vis use #module::#name;
+    }.into()
+}
+
+/// Emits a dependency on each file read by `include_str!` in a format string,
+/// so that the expansion is rebuilt when they change.
+fn emit_includes(includes: &[String]) -> TokenStream2 {
+    let includes = includes.iter().map(|path| quote!(const _: &str = include_str!(#path);));
+    quote!(#(#includes)*)
+}
+
+impl ToTokens for ScanInto {
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        // Locals use mixed-site hygiene, so that they can't be confused with
+        // variables named in the reader or target expressions.
+        let reader = Ident::new("reader", Span::mixed_site());
+        let count = Ident::new("count", Span::mixed_site());
+        let result = Ident::new("result", Span::mixed_site());
+        let reader_expr = &self.reader;
+
+        // Each place is evaluated once, even if several captures refer to it.
+        let places: Vec<_> = (0..self.targets.len())
+            .map(|idx| format_ident!("place_{}", idx, span = Span::mixed_site()))
+            .collect();
+        let targets = &self.targets;
+
+        // Each capture keeps its compiled regex in a `static` of its own. Items
+        // aren't hygienic, so their names are unlikely to be used elsewhere.
+        let mut sites = vec![];
+        let mut site = || {
+            let ident = format_ident!("__RESCAN_SITE_{}", sites.len());
+            sites.push(quote!(static #ident: CallSiteRegex = CallSiteRegex::new();));
+            ident
+        };
+
+        // Each segment breaks out of the `'scan` block when it fails, leaving
+        // the places that were scanned so far assigned.
+        let steps: Vec<_> = self.segments.iter().map(|seg| match seg {
+            Segment::Literal(lit) => quote! {
+                if let Err(error) = match_literal(#reader, #lit) {
+                    break 'scan Err(error.into());
+                }
+            },
+            Segment::Capture(IntoCapture::Skip { regex, flags }) => {
+                let site = site();
+                quote! {
+                    if let Err(error) = skip_pattern(#reader, &#site, #regex, #flags) {
+                        break 'scan Err(error);
+                    }
+                }
+            }
+            Segment::Capture(IntoCapture::Place { target, regex }) => {
+                let place = &places[*target];
+                let site = site();
+                let scan_expr = match regex {
+                    Some((regex, flags)) => quote!(scan_into_place_with(#reader, &mut *#place, &#site, #regex, #flags)),
+                    None => quote!(scan_into_place(#reader, &mut *#place, &#site)),
+                };
+                quote! {
+                    if let Err(error) = #scan_expr {
+                        break 'scan Err(error);
+                    }
+                    #count += 1;
+                }
+            }
+        }).collect();
+        let steps = join(&steps);
+        let sites = join(&sites);
+
+        let includes = emit_includes(&self.includes);
+
+        tokens.extend(quote! {
+            {
+                #includes
+                use rescan::_rescan_internal::*;
+                #sites
+
+                let #reader: &mut dyn std::io::BufRead = #reader_expr;
+                #(let #places = #targets;)*
+                let #reader = &mut ReaderInput(#reader);
+                let mut #count = 0_usize;
+                let #result: Result<(), rescan::Error> = 'scan: {
+                    #steps
+                    Ok(())
+                };
+                scan_into_count(#count, #result)
+            }
+        });
+    }
+}
+
 impl ToTokens for Abstract {
     fn to_tokens(&self, tokens: &mut TokenStream2) {
+        // Only rules with a regex are compiled, so each rule is mapped to its
+        // index in the regex array (or `None` for a nested scanner).
+        //
+        // Regexes other than literals are evaluated when the scanner is
+        // created, and their patterns are moved into the closure that compiles
+        // them. A scanner kept by a call site is created again for each new
+        // value of these patterns, which are evaluated every time it runs.
+        let patterns = Ident::new("patterns", Span::mixed_site());
         let mut regex_array = vec![];
-        for rule in self.rules.iter() {
-            let regex_expr = match rule {
-                Rule::Default { typ } => quote!(<#typ as DefaultScan>::DEFAULT_REGEX),
-                Rule::Custom { regex, typ: _ } |
-                Rule::Null { regex } => quote!(#regex),
+        let mut owned_patterns = vec![];
+        let mut pattern_exprs = vec![];
+        let regex_indices: Vec<_> = self.rules.iter().map(|rule| {
+            let (regex_expr, flags) = match rule {
+                Rule::Default { typ, flags } => (quote!(cached_regex(<#typ as DefaultScan>::DEFAULT_REGEX, #flags)), flags),
+                Rule::Custom { regex, typ: _, flags } |
+                Rule::Null { regex, flags } if is_constant(regex) => (quote!(cached_regex(#regex, #flags)), flags),
+                // Owned patterns are compiled once per scanner, since caching
+                // them would keep every distinct pattern for good.
+                Rule::Custom { regex, typ: _, flags } |
+                Rule::Null { regex, flags } => {
+                    let idx = owned_patterns.len();
+                    let ident = Ident::new(&format!("pattern_{}", idx), Span::mixed_site());
+                    owned_patterns.push(if self.call_site {
+                        quote!(let #ident: String = #patterns[#idx].to_owned();)
+                    } else {
+                        quote!(let #ident: String = AsRef::<str>::as_ref(&(#regex)).to_owned();)
+                    });
+                    pattern_exprs.push(regex);
+                    (quote!(compile_regex(&#ident, #flags)), flags)
+                }
+                Rule::Nested { .. } => return None,
             };
-            regex_array.push(quote!(Regex::new(#regex_expr),));
-        }
+            regex_array.push(quote!(#regex_expr,));
+            Some((regex_array.len() - 1, flags))
+        }).collect();
+
+        let context = match &self.context {
+            Some(context) => quote!(#context),
+            None => quote!(()),
+        };
 
         let mut literals = vec![];
         let mut matches = vec![];
         let mut captures = vec![];
+        let mut prints = vec![];
+        let mut nested = vec![];
+        let mut samples = vec![];
 
-        for seg in self.segments.iter() {
+        // Each segment breaks out of the `'scan` block with its index and
+        // error when it fails, so that the captures scanned so far are kept.
+        for (seg_idx, seg) in self.segments.iter().enumerate() {
             match seg {
                 Segment::Literal(lit) => {
                     let ident = format_ident!("lit_{}", literals.len());
                     literals.push(quote! {
                         let #ident = #lit;
                     });
+                    let match_fn = if self.ignore_case {
+                        quote!(match_literal_ignore_case)
+                    } else {
+                        quote!(match_literal)
+                    };
                     matches.push(quote! {
-                        match_literal(reader, #ident)?;
-                    });
-                }
-                Segment::Capture((None, rule)) => {
-                    matches.push(quote! {
-                        {
-                            let str_len = match_regex(reader, &regexes[#rule])?.len();
-                            advance_from_regex(reader, str_len);
+                        if let Err(error) = #match_fn(reader, #ident) {
+                            break 'scan Some((#seg_idx, error.into()));
                         }
                     });
+                    prints.push(quote!(out.write_str(#lit)?;));
+                    samples.push(quote!(out.push_str(#lit);));
                 }
-                Segment::Capture((Some(pos), rule)) => {
-                    let cap_ident = format_ident!("cap_{}", pos);
-                    let typ = match &self.rules[*rule] {
-                        Rule::Custom { typ, .. } |
-                        Rule::Default { typ } => typ.as_ref(),
+                Segment::Capture((pos, rule)) => {
+                    let regex_idx = regex_indices[*rule].map(|(idx, _)| idx);
+                    let cap_ident = format_ident!("cap_{}", pos.unwrap_or_default());
+                    let regex_source = regex_indices[*rule].map(|(idx, flags)| quote!(regexes[#idx].as_str(), #flags));
+                    samples.push(match (&self.rules[*rule], pos) {
+                        (Rule::Nested { scanner }, Some(_)) => quote! {
+                            let #cap_ident = (#scanner).sample_into(sampler, out);
+                        },
+                        (Rule::Nested { scanner }, None) => quote! {
+                            (#scanner).sample_into(sampler, out);
+                        },
+                        (_, None) => quote! {
+                            sampler.sample_regex(#regex_source, out);
+                        },
+                        (Rule::Custom { typ, .. } | Rule::Default { typ, .. }, Some(_)) => quote! {
+                            let start = out.len();
+                            sampler.sample_regex(#regex_source, out);
+                            let #cap_ident = <#typ as ScanWith<()>>::scan_with(&out[start..], &mut ()).ok();
+                        },
+                        (Rule::Null { .. }, Some(_)) => unreachable!("untyped capture"),
+                    });
+                    let scan_expr = match (&self.rules[*rule], pos) {
+                        (Rule::Nested { scanner }, _) => {
+                            let pos = match pos {
+                                Some(pos) => quote!(Some(#pos)),
+                                None => quote!(None),
+                            };
+                            // A scanner with a context passes it on to the
+                            // scanners it nests that take the same context.
+                            let scan = match &self.context {
+                                Some(_) => quote!((#scanner).scan_nested(reader, ctx)),
+                                None => quote!((#scanner).scan_from(reader)),
+                            };
+                            quote! {
+                                #scan.map_err(|error| Error::from_nested_error(#pos, error))
+                            }
+                        }
+                        (_, None) => quote! {
+                            skip_regex(reader, &regexes[#regex_idx]).map_err(Error::from)
+                        },
+                        (Rule::Custom { typ, .. } | Rule::Default { typ, .. }, Some(_)) => quote! {
+                            scan_capture(reader, &regexes[#regex_idx], |str| {
+                                <#typ as ScanWith<#context>>::scan_with(str, ctx)
+                            })
+                        },
                         // Parser ensures that only null captures can have null rules.
-                        Rule::Null { .. } => unreachable!("untyped capture"),
+                        (Rule::Null { .. }, Some(_)) => unreachable!("untyped capture"),
                     };
-                    matches.push(quote! {
-                        let #cap_ident = {
-                            let str = match_regex(reader, &regexes[#rule])?;
-                            let val = <#typ as Scan>::scan(str).map_err(Error::from_parse_error)?;
-                            let str_len = str.len();
-                            advance_from_regex(reader, str_len);
-                            val
-                        };
-                    });
-                    captures.push((*pos, quote!(#cap_ident), quote!(<#typ as Scan>::Output)));
+                    if let Some(pos) = pos {
+                        literals.push(quote!(let mut #cap_ident = None;));
+                        matches.push(quote! {
+                            match #scan_expr {
+                                Ok(val) => #cap_ident = Some(val),
+                                Err(error) => break 'scan Some((#seg_idx, error)),
+                            }
+                        });
+                        prints.push(match &self.rules[*rule] {
+                            Rule::Nested { scanner } => {
+                                nested.push(scanner);
+                                quote!((#scanner).print(#cap_ident, out)?;)
+                            }
+                            Rule::Custom { typ, .. } | Rule::Default { typ, .. } => {
+                                quote!(<#typ as Print>::print(#cap_ident, out)?;)
+                            }
+                            Rule::Null { .. } => unreachable!("untyped capture"),
+                        });
+                        captures.push((*pos, cap_ident));
+                    } else {
+                        matches.push(quote! {
+                            if let Err(error) = #scan_expr {
+                                break 'scan Some((#seg_idx, error));
+                            }
+                        });
+                    }
                 }
             }
         }
 
-        captures.sort_unstable_by(|(lhs, ..), (rhs, ..)| lhs.cmp(rhs));
-        let (captures, types): (Vec<_>, Vec<_>) = captures.into_iter()
-            .map(|(_num, cap, typ)| (cap, typ))
-            .unzip();
+        captures.sort_unstable_by(|(lhs, _), (rhs, _)| lhs.cmp(rhs));
+        // A failed scan gives its captures to the sink of `scan_partial`,
+        // in the output's `Captures::Partial` form. Tuples longer than those
+        // implementing `Captures` keep nothing.
+        let keep = match captures.as_slice() {
+            [(_num, cap)] => quote!(keep_whole(segment, #cap)),
+            captures if captures.len() <= 12 => {
+                let captures = captures.iter().map(|(_num, cap)| cap);
+                quote!(keep(segment, (#(#captures,)*)))
+            }
+            _ => quote!(keep_whole(segment, None)),
+        };
+        let some: Vec<_> = captures.iter()
+            .map(|(_num, cap)| quote!(Some(#cap)))
+            .collect();
+        let captures: Vec<_> = captures.into_iter()
+            .map(|(_num, cap)| quote!(#cap))
+            .collect();
+        // When every capture is scanned, no other combination is possible.
+        let unreachable = if captures.is_empty() {
+            quote!()
+        } else {
+            quote!(_ => unreachable!(),)
+        };
+
+        // A printable scanner destructures the value into its captures, then
+        // prints them between the literals.
+        let mut checks = quote!();
+        let printer = if self.printable {
+            let pattern = match captures.as_slice() {
+                [cap] => quote!(#cap),
+                _ => quote!((#(#captures),*)),
+            };
+            let prints = join(&prints);
+            // Nested scanners are checked when this one is created, rather
+            // than when it first prints.
+            checks = quote! {
+                #(assert!(
+                    (#nested).is_printable(),
+                    "nested scanner `{}` is not printable; add #[printable] to its format string",
+                    stringify!(#nested),
+                );)*
+            };
+            quote! {
+                .with_printer(|value, out| {
+                    let #pattern = value;
+                    #prints
+                    Ok(())
+                })
+            }
+        } else {
+            quote!()
+        };
+
+        // Only scanners without a context can scan their samples, so the
+        // others don't get a sampler.
+        let sampler = if self.context.is_none() {
+            let samples = join(&samples);
+            let value = match captures.as_slice() {
+                [cap] => quote!(#cap),
+                _ => quote!(Some((#(#captures?),*))),
+            };
+            quote! {
+                .with_sampler(|sampler, regexes, out| {
+                    #samples
+                    #value
+                })
+            }
+        } else {
+            quote!()
+        };
 
         let regex_array = join(&regex_array);
+        let owned_patterns = join(&owned_patterns);
         let literals = join(&literals);
         let matches = join(&matches);
+        let some = join_with(&some, quote!(,));
         let captures = join_with(&captures, quote!(,));
-        let types = join_with(&types, quote!(,));
 
+        // The scanning function is written as a closure so that its return
+        // type can be inferred, since the output types of nested scanners
+        // aren't known here. Unlike nested items, closures can also refer to
+        // the generic parameters of an enclosing function.
+        let includes = emit_includes(&self.includes);
         let output = quote! {
             {
-                use rescan::{Scan, DefaultScan, Scanner, Error, Result};
+                #includes
+                use rescan::{ScanWith, DefaultScan, ScanFrom, Print, Scanner, Error, Result};
                 use rescan::_rescan_internal::*;
 
-                fn build_regexes() -> Result<Vec<Regex>, RegexError> {
+                #owned_patterns
+                let build_regexes = move || -> Result<Vec<ScanRegex>, RegexError> {
                     [#regex_array].into_iter().collect()
-                }
+                };
 
-                fn scan(reader: &mut dyn std::io::BufRead, regexes: &[Regex]) -> Result<(#types)> {
+                #checks
+                Scanner::new(build_regexes, |reader: &mut dyn ScanInput, regexes: &[ScanRegex], ctx: &mut #context, partial| {
                     #literals
-                    #matches
-                    Ok((#captures))
+                    let failure: Option<(usize, Error)> = 'scan: {
+                        #matches
+                        None
+                    };
+                    match (failure, #captures) {
+                        (None, #some) => Ok((#captures)),
+                        (Some((segment, error)), #captures) => {
+                            if let Some(partial) = partial {
+                                partial.#keep;
+                            }
+                            Err(error)
+                        }
+                        #unreachable
+                    }
+                })
+                #printer
+                #sampler
+            }
+        };
+        // A call site keeps a scanner for each set of rule types, since a call
+        // site in a generic function may scan different types to the same
+        // output, and for each value of its patterns.
+        let output = if self.call_site {
+            let rule_types = self.rules.iter().filter_map(|rule| match rule {
+                Rule::Default { typ, .. } | Rule::Custom { typ, .. } => Some(typ),
+                Rule::Null { .. } | Rule::Nested { .. } => None,
+            });
+            let pattern_refs: Vec<_> = (0..pattern_exprs.len())
+                .map(|idx| format_ident!("pattern_ref_{}", idx, span = Span::mixed_site()))
+                .collect();
+            quote! {
+                {
+                    static __RESCAN_CALL_SITE: rescan::_rescan_internal::CallSite = rescan::_rescan_internal::CallSite::new();
+                    #(let #pattern_refs = &(#pattern_exprs);)*
+                    let #patterns: &[&str] = &[#(AsRef::<str>::as_ref(#pattern_refs)),*];
+                    __RESCAN_CALL_SITE.scanner::<(#(#rule_types,)*), _, _>(#patterns, || #output)
                 }
-
-                Scanner::new(build_regexes, scan)
             }
+        } else {
+            output
         };
         *tokens = quote!(#tokens #output);
     }
 }
 
+impl ToTokens for Flags {
+    /// Emits a `RegexFlags` value with these flags.
+    fn to_tokens(&self, tokens: &mut TokenStream2) {
+        let flags = [
+            (quote!(case_insensitive), self.case_insensitive),
+            (quote!(multi_line), self.multi_line),
+            (quote!(dot_matches_new_line), self.dot_matches_new_line),
+            (quote!(swap_greed), self.swap_greed),
+            (quote!(ignore_whitespace), self.ignore_whitespace),
+            (quote!(unicode), self.unicode),
+        ];
+        let fields = flags.into_iter().filter_map(|(field, value)| {
+            let value = value?;
+            Some(quote!(#field: Some(#value),))
+        });
+        let fields = join(&fields.collect::<Vec<_>>());
+        tokens.extend(quote!(RegexFlags { #fields ..RegexFlags::default() }));
+    }
+}
+
+/// Returns `true` if a regex expression is known to be constant: a literal or
+/// a call to `concat!`. Any other expression, even a path that names a
+/// constant, may depend on local variables.
+fn is_constant(expr: &syn::Expr) -> bool {
+    match expr {
+        syn::Expr::Lit(_) => true,
+        syn::Expr::Macro(mac) => mac.mac.path.is_ident("concat"),
+        syn::Expr::Group(group) => is_constant(&group.expr),
+        syn::Expr::Paren(paren) => is_constant(&paren.expr),
+        _ => false,
+    }
+}
+
 /// Join a slice of [`TokenStream`] into a single TokenStream.
 ///
 /// [`TokenStream`]: proc_macro2::TokenStream
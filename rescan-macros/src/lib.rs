@@ -4,15 +4,184 @@ mod parse;
 use proc_macro::TokenStream;
 use proc_macro_error::{proc_macro_error};
 
+/// Create a [`Scanner`] from a format string and a list of arguments.
+///
+/// Each `{}` in the format string is a capture, described by one of the
+/// arguments: either a type with a default regex (`u32`), or an explicit regex
+/// and type (`"[a-z]+" as String`).
+///
+/// An argument of the form `use EXPR` nests another scanner (or any value
+/// implementing `ScanFrom`), which reads its capture directly from the input.
+///
+/// Arguments may use the generic parameters of the enclosing function, as in
+/// `scanner!("{} {}", T, T)`, provided their errors are `'static` (as with
+/// `where T::Error: 'static`).
+///
+/// # Runtime regexes
+///
+/// A regex may be any expression whose value implements `AsRef<str>`, including
+/// one that refers to local variables, as in `config.id_pattern as String`.
+/// Such a pattern is evaluated when the scanner is created and owned by the
+/// scanner, which compiles it on first use. Literals and `concat!` are instead
+/// evaluated when the regexes are compiled, and are compiled only once per
+/// process for each pattern.
+///
+/// Macros that build their scanner only once and reuse it, such as `scan!`
+/// and `scanln_from!`, evaluate any other regex every time they run, and keep
+/// a scanner for each of its values.
+///
+/// # Format strings
+///
+/// Besides a string literal, the format string may be a call to `concat!` or
+/// `include_str!`, or the name of a constant declared with
+/// [`#[format_string]`](macro@format_string), so that a format can be shared
+/// between scanners or kept in its own file.
+///
+/// A constant can only be used this way within the crate that declares it,
+/// and must be imported along with the macro that `#[format_string]` declares
+/// beside it. A plain constant is rejected, since its value isn't known when
+/// the scanner is generated.
+///
+/// # Inline rules
+///
+/// A capture may give its type after a colon instead of taking an argument,
+/// as in `{:u32}`, optionally followed by a regex between slashes and any
+/// regex flags, as in `{:String:/[a-z]+/i}`. A slash within the regex is
+/// written `\/`. If a named argument has the same name as an inline type, the
+/// argument is used. An inline rule can be labelled, as in
+/// `{word:String:/[a-z]+/}`, so that later captures can refer to it with
+/// `{:word}`.
+///
+/// ```ignore
+/// scanner!("{:u32} -> {name:String:/[a-z]+/} <- {:name}");
+/// ```
+///
+/// # Regex flags
+///
+/// An argument may be preceded by a `#[flags = "..."]` attribute, using the
+/// same letters as the regex syntax `(?flags)`: `i` (case-insensitive), `m`
+/// (multi-line), `s` (`.` matches `\n`), `U` (swap greed), `x` (ignore
+/// whitespace) and `u` (Unicode). Flags after a `-` are cleared instead, so
+/// `#[flags = "i-u"]` matches case-insensitively with Unicode support off.
+///
+/// The format string itself accepts `#[flags = "i"]`, which makes its literal
+/// text match case-insensitively.
+///
+/// ```ignore
+/// scanner!(#[flags = "i"] "level={} msg={}", #[flags = "i"] "error|warn" as String, ".*" as String);
+/// ```
+///
+/// # Context
+///
+/// A `#[context(Type)]` attribute on the format string creates a scanner that
+/// takes a context of the given type in `Scanner::scan_with`. Its captures are
+/// then scanned with `ScanWith<Type>` rather than `Scan`, and the context is
+/// passed on to nested scanners that take the same context.
+///
+/// # Printing
+///
+/// A `#[printable]` attribute on the format string creates a scanner that can
+/// also print values with `Scanner::format`, writing each capture with its
+/// type's `Print` implementation between the literals of the format string.
+/// Nested scanners must be printable `Scanner`s themselves, which is checked
+/// when the scanner is created, and non-capturing specifiers are not allowed.
+///
+/// ```ignore
+/// let scanner = scanner!(#[printable] "{} -> {}", u32, Hex<u8>);
+/// assert_eq!("1 -> ff", scanner.format(&(1, 255)));
+/// ```
+///
+/// [`Scanner`]: https://docs.rs/rescan/latest/rescan/struct.Scanner.html
 #[proc_macro]
 #[proc_macro_error]
 pub fn scanner(input: TokenStream) -> TokenStream {
+    if let Some(forward) = parse::forward_format_const(input.clone(), quote::quote!(rescan::scanner), false) {
+        return forward.into();
+    }
     emit::emit(parse::parse(input))
 }
 
+/// Scan values from a reader into existing places.
+///
+/// The first argument is the reader, which must coerce to `&mut dyn BufRead`,
+/// and it is followed by a format string and a mutable reference to a place
+/// for each capture. The type of each capture is inferred from its place, and
+/// must implement `DefaultScan` with itself as the output type.
+///
+/// As with C's `scanf`, values are assigned as they are scanned, and scanning
+/// stops at the first input that doesn't match the format. The number of
+/// values assigned is returned. An error is only returned if reading fails or
+/// a regex fails to compile.
+///
+/// Captures are written `{}` for the next place, `{:N}` for the place at index
+/// `N`, or `{:/regex/}` for the next place with a custom regex. Text can be
+/// skipped with `{_:/regex/}`. Since types are inferred, captures can't have
+/// inline types or output positions.
+///
+/// ```ignore
+/// let (mut name, mut age) = (String::new(), 0_u32);
+/// let count = scan_into!(&mut input, "{:/[a-z]+ [a-z]+/} is {} years old", &mut name, &mut age)?;
+/// ```
+#[proc_macro]
+#[proc_macro_error]
+pub fn scan_into(input: TokenStream) -> TokenStream {
+    if let Some(forward) = parse::forward_format_const(input.clone(), quote::quote!(rescan::scan_into), true) {
+        return forward.into();
+    }
+    emit::emit_into(parse::parse_into(input))
+}
+
+/// Declare a constant format string that can be given to the scanning macros
+/// in place of a literal.
+///
+/// The constant's value may be a string literal, or a call to `concat!` or
+/// `include_str!`. Besides the constant, this declares a macro of the same
+/// name, through which the scanning macros read its value, so the two must be
+/// imported together (as `use` does for a single name). The constant can't be
+/// used by other crates.
+///
+/// ```ignore
+/// #[format_string]
+/// const RECORD: &str = concat!("{} ", include_str!("record.fmt"));
+///
+/// let scanner = scanner!(RECORD, u32, String);
+/// ```
+#[proc_macro_attribute]
+#[proc_macro_error]
+pub fn format_string(args: TokenStream, input: TokenStream) -> TokenStream {
+    emit::emit_format_const(parse::parse_format_const(args, input))
+}
+
 struct Abstract {
     segments: Vec<Segment<(Option<usize>, usize)>>,
     rules: Vec<Rule>,
+    ignore_case: bool,
+    context: Option<Box<syn::Type>>,
+    printable: bool,
+    /// Files read by `include_str!` in the format string.
+    includes: Vec<String>,
+    /// Whether the scanner is kept in a `CallSite`, for macros like `scan!`.
+    call_site: bool,
+}
+
+struct ScanInto {
+    reader: Box<syn::Expr>,
+    segments: Vec<Segment<IntoCapture>>,
+    targets: Vec<syn::Expr>,
+    includes: Vec<String>,
+}
+
+enum IntoCapture {
+    /// Scans the place at the given index, with an optional custom regex.
+    Place {
+        target: usize,
+        regex: Option<(Box<syn::Expr>, Flags)>,
+    },
+    /// Skips a match of the regex.
+    Skip {
+        regex: Box<syn::Expr>,
+        flags: Flags,
+    },
 }
 
 enum Segment<Cap> {
@@ -24,12 +193,32 @@ enum Segment<Cap> {
 enum Rule {
     Null {
         regex: Box<syn::Expr>,
+        flags: Flags,
     },
     Default {
         typ: Box<syn::Type>,
+        flags: Flags,
     },
     Custom {
         regex: Box<syn::Expr>,
         typ: Box<syn::Type>,
+        flags: Flags,
+    },
+    Nested {
+        scanner: Box<syn::Expr>,
     },
 }
+
+/// Regex flags attached to a rule with a `#[flags = "..."]` attribute.
+///
+/// Each flag is `None` unless explicitly set or cleared, in which case the
+/// regex library's default applies.
+#[derive(Debug, Default, PartialEq)]
+struct Flags {
+    case_insensitive: Option<bool>,
+    multi_line: Option<bool>,
+    dot_matches_new_line: Option<bool>,
+    swap_greed: Option<bool>,
+    ignore_whitespace: Option<bool>,
+    unicode: Option<bool>,
+}
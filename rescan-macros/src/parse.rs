@@ -1,7 +1,9 @@
 use syn::{self, spanned::Spanned as _};
 use proc_macro::TokenStream;
-use proc_macro_error::{emit_call_site_error, set_dummy, abort_if_dirty, abort_call_site, emit_error};
-use crate::{Abstract, Rule};
+use proc_macro2::{Group, Span, TokenStream as TokenStream2, TokenTree};
+use proc_macro_error::{set_dummy, abort_if_dirty, abort, abort_call_site, emit_error};
+use std::ops::Range;
+use crate::{Abstract, Flags, IntoCapture, Rule, ScanInto};
 
 pub(crate) fn parse(input: TokenStream) -> Abstract {
     // Until we have parsed the desired return types of the macro call, in case
@@ -16,15 +18,112 @@ pub(crate) fn parse(input: TokenStream) -> Abstract {
     Abstract::from(abs)
 }
 
+pub(crate) fn parse_into(input: TokenStream) -> ScanInto {
+    set_dummy(quote::quote!(rescan::internal::dummy()));
+
+    let parser = |input: syn::parse::ParseStream| {
+        let reader = input.parse()?;
+        let _comma: syn::Token![,] = input.parse()?;
+        let format_string: FormatString = input.parse()?;
+        let targets = if input.is_empty() {
+            vec![]
+        } else {
+            let _comma: syn::Token![,] = input.parse()?;
+            syn::punctuated::Punctuated::<syn::Expr, syn::Token![,]>::parse_terminated(input)?
+                .into_iter()
+                .collect()
+        };
+        Ok((reader, format_string, targets))
+    };
+    let (reader, format_string, targets): (_, _, Vec<_>) = match syn::parse::Parser::parse(parser, input) {
+        Ok(parsed) => parsed,
+        Err(err) => abort_call_site!("{}", err),
+    };
+    let segments = parse_format_string(&format_string)
+        .unwrap_or_else(|err| abort!(err.span, "{}", err.message));
+
+    // Each capture refers to a place, so only the forms that don't give a
+    // type or an output position are allowed.
+    let mut next_target = 0;
+    let mut used = vec![false; targets.len()];
+    let segments = segments.into_iter().filter_map(|seg| Some(match seg {
+        Segment::Literal(lit) => Segment::Literal(lit),
+        Segment::Capture(Capture { pos, label, rule, span }) => {
+            if label.is_some() || matches!(pos, CapturePos::Explicit(_)) {
+                emit_error!(span, "captures in `scan_into!` can't have output positions or labels");
+                return None;
+            }
+            let cap = match (pos, rule) {
+                (CapturePos::Null, CaptureRule::Inline(Rule::Null { regex, flags })) => IntoCapture::Skip { regex, flags },
+                (CapturePos::Null, _) => {
+                    emit_error!(span, "non-capturing specifiers in `scan_into!` must have an inline regex";
+                        help = "try skipping text like `{_:/[0-9]+/}`");
+                    return None;
+                }
+                (_, CaptureRule::Implicit) => {
+                    next_target += 1;
+                    IntoCapture::Place { target: next_target - 1, regex: None }
+                }
+                (_, CaptureRule::Positional(target)) => IntoCapture::Place { target, regex: None },
+                (_, CaptureRule::Inline(Rule::Null { regex, flags })) => {
+                    next_target += 1;
+                    IntoCapture::Place { target: next_target - 1, regex: Some((regex, flags)) }
+                }
+                (_, CaptureRule::Named(_) | CaptureRule::Inline(_)) => {
+                    emit_error!(span, "captures in `scan_into!` can't have types";
+                        note = "the type of each capture is inferred from its place");
+                    return None;
+                }
+            };
+            if let IntoCapture::Place { target, .. } = cap {
+                match used.get_mut(target) {
+                    Some(used) => *used = true,
+                    None => {
+                        emit_error!(span, "invalid reference to place {} (only {} were provided)", target, targets.len());
+                        return None;
+                    }
+                }
+            }
+            Segment::Capture(cap)
+        }
+    })).collect();
+
+    for (target, _) in targets.iter().zip(&used).filter(|(_, used)| !**used) {
+        emit_error!(target.span(), "unused place");
+    }
+    abort_if_dirty();
+
+    ScanInto {
+        reader: Box::new(reader),
+        segments,
+        targets,
+        includes: format_string.includes,
+    }
+}
+
 impl From<Concrete> for Abstract {
-    fn from(Concrete { segments, positional_rules, named_rules }: Concrete) -> Self {
+    fn from(Concrete { mut segments, format_span, includes, positional_rules, mut named_rules, ignore_case, context, printable, call_site }: Concrete) -> Self {
+        // Inline rules of labelled captures become named rules, so that other
+        // captures can refer to them by name.
+        for seg in &mut segments {
+            if let Segment::Capture(Capture { label: Some(label), rule, .. }) = seg {
+                if let CaptureRule::Inline(inline) = std::mem::replace(rule, CaptureRule::Named(label.clone())) {
+                    named_rules.push((label.clone(), inline));
+                }
+            }
+        }
+
+        // Other inline rules are added after the named rules.
+        let num_rules = positional_rules.len() + named_rules.len();
+        let mut inline_rules = vec![];
         let mut pos_idx = 0;
         let mut rule_idx = 0;
-        let mut bad_positions = vec![];
-        let mut bad_names = vec![];
+        // The span of each remaining capture, in order.
+        let mut capture_spans = vec![];
         let segments: Vec<_> = segments.into_iter().filter_map(|seg| Some(match seg {
             Segment::Literal(lit) => Segment::Literal(lit),
             Segment::Capture(cap) => {
+                let span = cap.span;
                 let pos = match cap.pos {
                     CapturePos::Null => None,
                     CapturePos::Explicit(pos) => Some(pos),
@@ -38,71 +137,54 @@ impl From<Concrete> for Abstract {
                         // Ensure that implicit positional references are within range.
                         let idx = rule_idx;
                         rule_idx += 1;
-                        let num_positions = positional_rules.len();
-                        if idx >= num_positions {
-                            bad_positions.push((idx,));
-                        }
+                        check_positional(idx, positional_rules.len(), span);
                         idx
                     }
                     CaptureRule::Positional(rule) => {
                         // Ensure that explicit positional references are within range.
-                        let num_positions = positional_rules.len();
-                        if rule >= num_positions {
-                            bad_positions.push((rule,));
-                        }
+                        check_positional(rule, positional_rules.len(), span);
                         rule
                     }
                     CaptureRule::Named(name) => {
                         let idx = named_rules.iter().position(|(rule_name, _)| rule_name == &name);
                         if let Some(idx) = idx {
                             positional_rules.len() + idx
+                        } else if let Ok(typ) = parse_inline_type(&name, span) {
+                            // A name that isn't an argument is an inline type, like `{:u32}`.
+                            inline_rules.push(Rule::Default { typ, flags: Flags::default() });
+                            num_rules + inline_rules.len() - 1
                         } else {
                             // Ensure that the referenced named argument exists.
-                            bad_names.push((name,));
+                            emit_error!(span, "there is no argument named `{}`", name);
                             return None; // TODO: Somehow keep this capture around for further error checking,
                             // even though we don't have an obvious rule to pair it with.
                         }
                     }
+                    CaptureRule::Inline(rule) => {
+                        inline_rules.push(rule);
+                        num_rules + inline_rules.len() - 1
+                    }
                 };
+                capture_spans.push(span);
                 Segment::Capture((pos, rule))
             }
         })).collect();
 
-        // Report any invalid references to positional rules.
-        if let Some((last, rest)) = bad_positions.split_last() {
-            let bad_refs = match rest {
-                [] => format!("argument {}", last.0),
-                [first] => format!("arguments {} and {}", first.0, last.0),
-                _ => {
-                    format!("arguments {}and {}", rest.iter().fold("".into(), |str, bad_ref| format!("{}{}, ", str, bad_ref.0)), last.0)
-                }
-            };
-            let args_provided = match positional_rules.len() {
-                1 => format!("only 1 argument was provided"),
-                n => format!("only {} arguments were provided", n),
-            };
-
-            let msg = format!("invalid reference to positional {} ({})", bad_refs, args_provided);
-            emit_call_site_error!(msg);
-        }
-
-        // Report any invalid references to named rules.
-        for (bad_name,) in bad_names {
-            let msg = format!("there is no argument named `{}`", bad_name);
-            emit_call_site_error!(msg);
-        }
-
         // Ensure that outputs are unique.
         let mut outputs: Vec<_> = iter_captures(&segments)
-            .filter_map(|(pos, _rule)| *pos)
+            .zip(&capture_spans)
+            .filter_map(|(&(pos, _rule), &span)| Some((pos?, span)))
             .collect();
-        outputs.sort(); // Stable so we can highlight the first occurrence among duplicates.
-        for (first, rest) in equal_ranges(&outputs).filter_map(<[_]>::split_first) {
-            for dup in rest {
-                emit_call_site_error!("duplicate reference to capture position {}", dup;
-                    note = "first defined here: {}", first);
+        outputs.sort_by_key(|&(pos, _span)| pos); // Stable so we can highlight the first occurrence among duplicates.
+        for range in outputs.chunk_by(|(lhs, _), (rhs, _)| lhs == rhs) {
+            if let Some(((_, first), rest)) = range.split_first() {
+                for &(dup, span) in rest {
+                    emit_error!(span, "duplicate reference to capture position {}", dup;
+                        note = *first => "first defined here");
+                }
             }
         }
+        let outputs: Vec<_> = outputs.into_iter().map(|(pos, _span)| pos).collect();
 
         // Ensure that outputs cover the range 0..n, where n is the number of outputs.
         let missing_outputs: Vec<_> = (0..outputs.len())
@@ -118,47 +200,59 @@ impl From<Concrete> for Abstract {
                 1 => format!("there was 1 capture spec"),
                 n => format!("there were {} capture specs", n),
             };
-            emit_call_site_error!("missing {} ({})", missing_outputs, captures_specified);
+            emit_error!(format_span, "missing {} ({})", missing_outputs, captures_specified);
         }
 
         // Ensure that all named rules are unique.
         let mut names: Vec<_> = named_rules
             .iter()
-            .map(|(name, _)| name)
+            .map(|(name, rule)| (name, rule.span()))
             .collect();
-        names.sort(); // Stable so we can highlight the first occurrence among duplicates.
-        for (first, rest) in equal_ranges(&names).filter_map(<[_]>::split_first) {
-            for dup in rest {
-                emit_call_site_error!("duplicate argument name `{}`", dup;
-                    note = "first defined here: {}", first);
+        names.sort_by_key(|&(name, _span)| name); // Stable so we can highlight the first occurrence among duplicates.
+        for range in names.chunk_by(|(lhs, _), (rhs, _)| lhs == rhs) {
+            if let Some(((_, first), rest)) = range.split_first() {
+                for &(dup, span) in rest {
+                    emit_error!(span, "duplicate argument name `{}`", dup;
+                        note = *first => "first defined here");
+                }
             }
         }
 
         let mut rules = positional_rules;
         rules.extend(named_rules.into_iter().map(|(_name, rule)| rule));
+        rules.extend(inline_rules);
 
         // Ensure that all rules are referenced.
         let mut rule_refs: Vec<_> = iter_captures(&segments)
             .map(|&(_pos, rule)| rule)
             .collect();
         rule_refs.sort_unstable();
-        for (idx, _rule) in rules.iter().enumerate() {
+        for (idx, rule) in rules.iter().enumerate() {
             if rule_refs.binary_search(&idx).is_err() {
-                emit_call_site_error!("unused argument: {}", idx);
+                emit_error!(rule.span(), "unused argument: {}", idx);
             }
         }
 
         // Ensure that null rules are only referenced by null captures.
-        for &(pos, rule) in iter_captures(&segments) {
+        for (&(pos, rule), &span) in iter_captures(&segments).zip(&capture_spans) {
             if pos.is_some() {
                 let rule = &rules[rule];
                 if let Rule::Null { .. } = rule {
-                    emit_call_site_error!("untyped arguments cannot be used in captures";
+                    emit_error!(rule.span(), "untyped arguments cannot be used in captures";
+                        note = span => "used in this capture";
                         help = "try specifying an output type for the argument or using a non-capturing specifier");
                 }
             }
         }
 
+        // Ensure that a printable scanner has a value to print for every capture.
+        if printable {
+            for (_cap, &span) in iter_captures(&segments).zip(&capture_spans).filter(|((pos, _rule), _span)| pos.is_none()) {
+                emit_error!(span, "non-capturing specifiers cannot be printed";
+                    help = "try capturing the value or writing it as a literal");
+            }
+        }
+
         // At this point, we should have caught all syntax errors.
         // Stop here if any such errors have occurred.
         abort_if_dirty();
@@ -166,31 +260,35 @@ impl From<Concrete> for Abstract {
         Self {
             segments,
             rules,
+            ignore_case,
+            context,
+            printable,
+            includes,
+            call_site,
         }
     }
 }
 
-// TODO: Replace this with [`group_by`] when it stabilizes:
-// https://doc.rust-lang.org/stable/std/primitive.slice.html#method.group_by
-// https://github.com/rust-lang/rust/issues/80552
-struct EqualRanges<'a, T> {
-    arr: &'a [T],
-}
-impl<'a, T: PartialEq> Iterator for EqualRanges<'a, T> {
-    type Item = &'a [T];
-    fn next(&mut self) -> Option<Self::Item> {
-        let (first, rest) = self.arr.split_first()?;
-        let mut idx = 0;
-        while idx < rest.len() && first.eq(&rest[idx]) {
-            idx += 1;
-        }
-        let (range, rest) = self.arr.split_at(idx + 1);
-        self.arr = rest;
-        Some(range)
+/// Reports a reference to a positional argument that doesn't exist.
+fn check_positional(idx: usize, num_positions: usize, span: Span) {
+    if idx >= num_positions {
+        let args_provided = match num_positions {
+            1 => "only 1 argument was provided".to_string(),
+            n => format!("only {} arguments were provided", n),
+        };
+        emit_error!(span, "invalid reference to positional argument {} ({})", idx, args_provided);
     }
 }
-fn equal_ranges<T: PartialEq>(arr: &[T]) -> EqualRanges<T> {
-    EqualRanges { arr }
+
+impl Rule {
+    /// Returns the span of the argument this rule was given by.
+    fn span(&self) -> Span {
+        match self {
+            Rule::Null { regex, .. } | Rule::Custom { regex, .. } => regex.span(),
+            Rule::Default { typ, .. } => typ.span(),
+            Rule::Nested { scanner } => scanner.span(),
+        }
+    }
 }
 
 fn iter_captures<Cap>(segments: &[Segment<Cap>]) -> impl Iterator<Item = &Cap> {
@@ -202,15 +300,27 @@ fn iter_captures<Cap>(segments: &[Segment<Cap>]) -> impl Iterator<Item = &Cap> {
 
 struct Concrete {
     pub segments: Vec<Segment>,
+    format_span: Span,
+    includes: Vec<String>,
     positional_rules: Vec<Rule>,
     named_rules: Vec<(String, Rule)>,
+    ignore_case: bool,
+    context: Option<Box<syn::Type>>,
+    printable: bool,
+    /// Set by the hidden `#[call_site]` attribute of macros that keep their
+    /// scanner in a `CallSite`.
+    call_site: bool,
 }
 
 type Segment<Cap = Capture> = crate::Segment<Cap>;
 
 struct Capture {
     pos: CapturePos,
+    /// The name given to an inline rule, as in `{name:Type}`.
+    label: Option<String>,
     rule: CaptureRule,
+    /// The span of the capture within the format string.
+    span: Span,
 }
 
 enum CapturePos {
@@ -223,6 +333,7 @@ enum CaptureRule {
     Implicit,
     Positional(usize),
     Named(String),
+    Inline(Rule),
 }
 
 struct Arg {
@@ -232,9 +343,47 @@ struct Arg {
 
 impl syn::parse::Parse for Concrete {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        let format_string: syn::LitStr = input.parse()?;
-        let segments = parse_format_string(format_string)
-            .unwrap_or_else(|err| abort_call_site!("{}", err));
+        // The format string may be preceded by `#[flags = "i"]` to match its
+        // literals case-insensitively, by `#[context(Type)]` to scan with a
+        // context, and by `#[printable]` to also print values.
+        let mut ignore_case = false;
+        let mut context = None;
+        let mut printable = false;
+        let mut call_site = false;
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            if attr.path.is_ident("call_site") {
+                call_site = true;
+                continue;
+            }
+            if attr.path.is_ident("printable") {
+                if printable {
+                    emit_error!(attr.span(), "duplicate printable attribute");
+                }
+                if !attr.tokens.is_empty() {
+                    emit_error!(attr.tokens.span(), "the printable attribute takes no arguments");
+                }
+                printable = true;
+                continue;
+            }
+            if attr.path.is_ident("context") {
+                if context.is_some() {
+                    emit_error!(attr.span(), "duplicate context attribute");
+                }
+                context = Some(Box::new(attr.parse_args()?));
+                continue;
+            }
+            let flags = parse_flags(&attr);
+            if flags.multi_line.is_some() || flags.dot_matches_new_line.is_some() || flags.swap_greed.is_some()
+                || flags.ignore_whitespace.is_some() || flags.unicode.is_some() {
+                emit_error!(attr.span(), "only the `i` flag can be applied to the format string";
+                    help = "attach other flags to the arguments they apply to");
+            }
+            ignore_case = flags.case_insensitive.unwrap_or(ignore_case);
+        }
+
+        let format_string: FormatString = input.parse()?;
+        let segments = parse_format_string(&format_string)
+            .unwrap_or_else(|err| abort!(err.span, "{}", err.message));
         let mut positional_rules = vec![];
         let mut named_rules = vec![];
         if !input.is_empty() {
@@ -248,7 +397,7 @@ impl syn::parse::Parse for Concrete {
                         // TODO: This doesn't need to abort; it could just emit instead.
                         // But as is, continuing after such an error could cause issues with captures
                         // using incorrect positional arguments and yielding incorrect errors.
-                        abort_call_site!("positional arguments must be before named arguments");
+                        abort!(rule.span(), "positional arguments must be before named arguments");
                     }
                     positional_rules.push(rule);
                 }
@@ -256,17 +405,29 @@ impl syn::parse::Parse for Concrete {
         }
         Ok(Self {
             segments,
+            format_span: format_string.lit.span(),
+            includes: format_string.includes,
             positional_rules,
             named_rules,
+            ignore_case,
+            context,
+            printable,
+            call_site,
         })
     }
 }
 
 impl syn::parse::Parse for Arg {
     fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
-        use syn::{ExprCast, Ident, Token, Type};
+        use syn::{Attribute, ExprCast, Ident, Token, Type};
+
+        // Collect the regex flags from any leading `#[flags = "..."]` attributes.
+        let mut flags = Flags::default();
+        for attr in input.call(Attribute::parse_outer)? {
+            flags.extend(parse_flags(&attr));
+        }
 
-        // First test if there is a leading `ident =`, which uniquely identifies
+        // Next test if there is a leading `ident =`, which uniquely identifies
         // a named argument.
         let name = if input.peek(Ident) && input.peek2(Token![=]) {
             let name: Ident = input.parse()?;
@@ -276,6 +437,19 @@ impl syn::parse::Parse for Arg {
             None
         };
 
+        // A leading `use` marks a nested scanner, which takes no regex or type.
+        if input.peek(Token![use]) {
+            let use_token: Token![use] = input.parse()?;
+            if flags != Flags::default() {
+                emit_error!(use_token.span(), "regex flags cannot be applied to nested scanners");
+            }
+            let scanner = Box::new(input.parse()?);
+            return Ok(Self {
+                name,
+                rule: Rule::Nested { scanner },
+            });
+        }
+
         // Try to parse from here as a type. If successful, with a follow set
         // of only ",", return a type-only argument. Otherwise, backtrack and
         // try something else.
@@ -284,7 +458,7 @@ impl syn::parse::Parse for Arg {
             let typ = Box::new(input.parse()?);
             return Ok(Self {
                 name,
-                rule: Rule::Default { typ },
+                rule: Rule::Default { typ, flags },
             });
         }
 
@@ -302,11 +476,12 @@ impl syn::parse::Parse for Arg {
         // Check for the null type (written `_`) that can only be used with null
         // captures (those which don't actually extract a value).
         let rule = if let Type::Infer(_) = *typ {
-            Rule::Null { regex }
+            Rule::Null { regex, flags }
         } else {
             Rule::Custom {
                 regex,
                 typ,
+                flags,
             }
         };
 
@@ -317,19 +492,243 @@ impl syn::parse::Parse for Arg {
     }
 }
 
-fn parse_format_string(input: syn::LitStr) -> Result<Vec<Segment>, String> {
-    FormatStringParser::new(&input.value()).parse()
+/// Parses a `#[flags = "..."]` attribute.
+///
+/// The flags are written as in the regex syntax `(?flags)`: each letter sets
+/// a flag, and letters following a `-` clear it instead.
+fn parse_flags(attr: &syn::Attribute) -> Flags {
+    let lit = match attr.parse_meta() {
+        Ok(syn::Meta::NameValue(syn::MetaNameValue { path, lit: syn::Lit::Str(lit), .. })) if path.is_ident("flags") => lit,
+        _ => {
+            emit_error!(attr.span(), "unsupported attribute";
+                help = "regex flags are specified like `#[flags = \"im-u\"]`");
+            return Flags::default();
+        }
+    };
+
+    parse_flag_letters(&lit.value(), |ch| {
+        emit_error!(lit.span(), "unrecognized flag '{}'", ch;
+            help = "valid flags are 'i', 'm', 's', 'U', 'x' and 'u'");
+    })
+}
+
+/// Parses flag letters such as `"im-u"`, calling `unrecognized` with any
+/// letter that isn't a flag.
+fn parse_flag_letters(letters: &str, mut unrecognized: impl FnMut(char)) -> Flags {
+    let mut flags = Flags::default();
+    let mut enable = true;
+    for ch in letters.chars() {
+        let flag = match ch {
+            '-' if enable => {
+                enable = false;
+                continue;
+            }
+            'i' => &mut flags.case_insensitive,
+            'm' => &mut flags.multi_line,
+            's' => &mut flags.dot_matches_new_line,
+            'U' => &mut flags.swap_greed,
+            'x' => &mut flags.ignore_whitespace,
+            'u' => &mut flags.unicode,
+            _ => {
+                unrecognized(ch);
+                continue;
+            }
+        };
+        *flag = Some(enable);
+    }
+    flags
+}
+
+impl Flags {
+    /// Overrides these flags with any that are set in `other`.
+    fn extend(&mut self, other: Flags) {
+        self.case_insensitive = other.case_insensitive.or(self.case_insensitive);
+        self.multi_line = other.multi_line.or(self.multi_line);
+        self.dot_matches_new_line = other.dot_matches_new_line.or(self.dot_matches_new_line);
+        self.swap_greed = other.swap_greed.or(self.swap_greed);
+        self.ignore_whitespace = other.ignore_whitespace.or(self.ignore_whitespace);
+        self.unicode = other.unicode.or(self.unicode);
+    }
+}
+
+/// The format string of a scanning macro: a string literal, or a `concat!`
+/// or `include_str!` call, which is expanded here.
+struct FormatString {
+    lit: syn::LitStr,
+    /// Whether `lit` appears as written in the source, so that its subspans
+    /// can be located.
+    in_source: bool,
+    /// The paths of the files read by `include_str!`.
+    includes: Vec<String>,
+}
+
+impl syn::parse::Parse for FormatString {
+    fn parse(input: syn::parse::ParseStream) -> syn::Result<Self> {
+        if input.peek(syn::LitStr) {
+            return Ok(Self { lit: input.parse()?, in_source: true, includes: vec![] });
+        }
+        let expr: syn::Expr = input.parse()?;
+        let mut value = String::new();
+        let mut includes = vec![];
+        expand_string(&expr, &mut value, &mut includes)?;
+        Ok(Self {
+            lit: syn::LitStr::new(&value, expr.span()),
+            in_source: false,
+            includes,
+        })
+    }
+}
+
+/// Appends the value of a string literal, `concat!` or `include_str!` call to
+/// `value`, recording the paths of any files read.
+fn expand_string(expr: &syn::Expr, value: &mut String, includes: &mut Vec<String>) -> syn::Result<()> {
+    use syn::{punctuated::Punctuated, Expr, Lit, Token};
+
+    let unsupported = || syn::Error::new(expr.span(), "expected a format string: a string literal, `concat!`, `include_str!` or a constant declared with `#[format_string]`");
+    match expr {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(lit) => value.push_str(&lit.value()),
+            _ => return Err(unsupported()),
+        },
+        Expr::Macro(mac) if mac.mac.path.is_ident("concat") => {
+            let args = mac.mac.parse_body_with(Punctuated::<Expr, Token![,]>::parse_terminated)?;
+            for arg in &args {
+                expand_concat_arg(arg, value, includes)?;
+            }
+        }
+        Expr::Macro(mac) if mac.mac.path.is_ident("include_str") => {
+            let path: syn::LitStr = mac.mac.parse_body()?;
+            let path = resolve_include(&path);
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|err| syn::Error::new(mac.span(), format!("couldn't read {}: {}", path.display(), err)))?;
+            value.push_str(&contents);
+            // The path is made absolute, since the expansion may be in
+            // another file.
+            let path = std::fs::canonicalize(&path).unwrap_or(path);
+            includes.push(path.to_string_lossy().into_owned());
+        }
+        Expr::Group(group) => expand_string(&group.expr, value, includes)?,
+        _ => return Err(unsupported()),
+    }
+    Ok(())
+}
+
+/// Appends the text of an argument to `concat!`, which may be any literal.
+fn expand_concat_arg(arg: &syn::Expr, value: &mut String, includes: &mut Vec<String>) -> syn::Result<()> {
+    use syn::{Expr, Lit, UnOp};
+
+    match arg {
+        Expr::Lit(lit) => match &lit.lit {
+            Lit::Str(lit) => value.push_str(&lit.value()),
+            Lit::Char(lit) => value.push(lit.value()),
+            Lit::Int(lit) => value.push_str(lit.base10_digits()),
+            Lit::Float(lit) => value.push_str(lit.base10_digits()),
+            Lit::Bool(lit) => value.push_str(if lit.value { "true" } else { "false" }),
+            _ => return Err(syn::Error::new(lit.span(), "cannot concatenate a byte string literal")),
+        },
+        Expr::Unary(unary) if matches!(unary.op, UnOp::Neg(_)) && matches!(&*unary.expr, Expr::Lit(_)) => {
+            value.push('-');
+            expand_concat_arg(&unary.expr, value, includes)?;
+        }
+        _ => expand_string(arg, value, includes)?,
+    }
+    Ok(())
+}
+
+/// Returns the path of a file given to `include_str!`, which is relative to
+/// the file containing the call.
+fn resolve_include(path: &syn::LitStr) -> std::path::PathBuf {
+    let relative = std::path::PathBuf::from(path.value());
+    let dir = path.span().unwrap().local_file()
+        .and_then(|file| file.parent().map(|dir| dir.to_path_buf()))
+        .or_else(|| std::env::var_os("CARGO_MANIFEST_DIR").map(Into::into))
+        .unwrap_or_default();
+    dir.join(relative)
+}
+
+/// Returns a call to the macro declared by `#[format_string]` alongside the
+/// constant given as the format string, if any. That macro expands to a call
+/// to `mac` with the constant's value in place of its path. A constant
+/// declared without the attribute is reported at its path instead.
+///
+/// The format string follows any attributes, and the reader if `has_reader`.
+pub(crate) fn forward_format_const(input: TokenStream, mac: TokenStream2, has_reader: bool) -> Option<TokenStream2> {
+    use quote::ToTokens as _;
+    use syn::Token;
+
+    let parser = |input: syn::parse::ParseStream| -> syn::Result<Option<TokenStream2>> {
+        let mut before = TokenStream2::new();
+        if has_reader {
+            input.parse::<syn::Expr>()?.to_tokens(&mut before);
+            input.parse::<Token![,]>()?.to_tokens(&mut before);
+        }
+        for attr in input.call(syn::Attribute::parse_outer)? {
+            attr.to_tokens(&mut before);
+        }
+        if input.peek(syn::LitStr) {
+            return Ok(None);
+        }
+        let path: syn::Path = input.parse()?;
+        // A macro call, such as `concat!(...)`, is expanded directly.
+        if !input.is_empty() && !input.peek(Token![,]) {
+            return Ok(None);
+        }
+        let after: TokenStream2 = input.parse()?;
+        // The path is imported under a fixed name, which falls back to a
+        // macro reporting the missing attribute if it names a plain constant.
+        let name = quote::format_ident!("__rescan_format", span = path.span());
+        Ok(Some(quote::quote!({
+            #[allow(unused_imports)]
+            use rescan::_rescan_internal::format_fallback::*;
+            {
+                #[allow(unused_imports)]
+                use #path as #name;
+                #name!([#mac], [#before], [#after], [#path])
+            }
+        })))
+    };
+    syn::parse::Parser::parse(parser, input).ok().flatten()
+}
+
+/// Parses a constant declared with `#[format_string]`, whose value must be a
+/// format string.
+pub(crate) fn parse_format_const(args: TokenStream, input: TokenStream) -> syn::ItemConst {
+    if !args.is_empty() {
+        abort!(TokenStream2::from(args), "the format_string attribute takes no arguments");
+    }
+    let item: syn::ItemConst = match syn::parse(input) {
+        Ok(item) => item,
+        Err(err) => abort!(err.span(), "{}", err;
+            help = "the format_string attribute applies to constants like `const FORMAT: &str = \"...\";`"),
+    };
+    let mut value = String::new();
+    if let Err(err) = expand_string(&item.expr, &mut value, &mut vec![]) {
+        abort!(err.span(), "{}", err);
+    }
+    item
+}
+
+fn parse_format_string(input: &FormatString) -> Result<Vec<Segment>, FormatError> {
+    FormatStringParser::new(input).parse()
+}
+
+/// An error in the format string, located at the offending text.
+struct FormatError {
+    span: Span,
+    message: String,
 }
 
 struct FormatStringParser<'s> {
-    source: &'s str,
+    format: &'s FormatString,
+    source: String,
     pos: usize,
     output: Vec<Segment>,
 }
 impl<'s> FormatStringParser<'s> {
-    fn new(source: &'s str) -> Self {
+    fn new(format: &'s FormatString) -> Self {
         Self {
-            source,
+            format,
+            source: format.lit.value(),
             pos: 0,
             output: vec![],
         }
@@ -337,7 +736,30 @@ impl<'s> FormatStringParser<'s> {
     fn remainder(&self) -> &str {
         &self.source[self.pos..]
     }
-    fn parse(mut self) -> Result<Vec<Segment>, String> {
+    /// Returns the span of the given bytes of the format string.
+    fn span(&self, range: Range<usize>) -> Span {
+        if self.format.in_source {
+            value_subspan(&self.format.lit, range)
+        } else {
+            self.format.lit.span()
+        }
+    }
+    /// Returns an error located at the given bytes of the format string.
+    fn error(&self, range: Range<usize>, message: impl Into<String>) -> FormatError {
+        FormatError {
+            span: self.span(range),
+            message: message.into(),
+        }
+    }
+    /// Returns an error located at the next character, or at the end of the
+    /// format string.
+    fn unexpected(&self, start: usize) -> FormatError {
+        match self.remainder().chars().next() {
+            Some(next) => self.error(self.pos..self.pos + next.len_utf8(), format!("Unexpected character '{}' in format string", next)),
+            None => self.error(start..self.pos, "Unexpected end of format string"),
+        }
+    }
+    fn parse(mut self) -> Result<Vec<Segment>, FormatError> {
         loop {
             if self.pos >= self.source.len() { break; }
             self.parse_literal()?;
@@ -346,14 +768,14 @@ impl<'s> FormatStringParser<'s> {
         }
         Ok(self.output)
     }
-    fn parse_literal(&mut self) -> Result<(), String> {
+    fn parse_literal(&mut self) -> Result<(), FormatError> {
         let mut result = String::new();
         let source = &self.source.as_bytes();
         while let Some(&ch) = source.get(self.pos) {
             match ch as char {
                 '{' | '}' if source.get(self.pos + 1) == Some(&ch) => self.pos += 1,
                 '{' => break,
-                '}' => return Err("Unmatched '}' in format string".into()),
+                '}' => return Err(self.error(self.pos..self.pos + 1, "Unmatched '}' in format string")),
                 _ => (),
             }
             result.push(ch as char);
@@ -364,92 +786,238 @@ impl<'s> FormatStringParser<'s> {
         }
         Ok(())
     }
-    fn parse_capture(&mut self) -> Result<(), String> {
+    fn parse_capture(&mut self) -> Result<(), FormatError> {
+        let start = self.pos;
+
         // First check for the common case.
         if self.remainder().starts_with("{}") {
             self.pos += 2;
             self.output.push(Segment::Capture(Capture {
                 pos: CapturePos::Implicit,
+                label: None,
                 rule: CaptureRule::Implicit,
+                span: self.span(start..self.pos),
             }));
             return Ok(());
         }
 
         assert!(self.remainder().starts_with("{"));
         self.pos += 1;
+        let unmatched = |parser: &Self| parser.error(start..start + 1, "Unmatched '{' in format string");
 
         // Extract the first field: the output position of this capture.
         // It will be one of:
         //   - Null ("_"), meaning the regex will be matched, but not captured
         //   - Implicit (""), meaning the position of the output will be sequential from the preceding implicit capture
         //   - Explicit ("2"), meaning the captured value will be output in the given position, e.g. 2
+        //   - Labelled ("word"), meaning an implicit position, with an inline rule that other captures can refer to
         let source = self.remainder();
         let end_of_pos = source
-            .find(|ch: char| ch != '_' && !ch.is_ascii_digit())
-            .ok_or_else(|| String::from("Unmatched '{' in format string"))?;
+            .find(|ch: char| ch != '_' && !ch.is_ascii_alphanumeric())
+            .ok_or_else(|| unmatched(self))?;
         let pos = &source[..end_of_pos];
-        let pos = match pos {
-            "_" => CapturePos::Null,
-            "" => CapturePos::Implicit,
+        let (pos, label) = match pos {
+            "_" => (CapturePos::Null, None),
+            "" => (CapturePos::Implicit, None),
             _ => if let Ok(num) = pos.parse() {
-                CapturePos::Explicit(num)
+                (CapturePos::Explicit(num), None)
+            } else if pos.starts_with(|ch: char| ch == '_' || ch.is_ascii_alphabetic()) {
+                (CapturePos::Implicit, Some(pos.to_string()))
             } else {
-                return Err(format!("Invalid position: '{}'", pos));
+                return Err(self.error(self.pos..self.pos + end_of_pos, format!("Invalid position: '{}'", pos)));
             }
         };
         self.pos += end_of_pos;
 
         // Exit early if there are no remaining fields. We'll assume the rule used corresponds to its position.
         let source = self.remainder();
-        if source.starts_with('}') {
+        if source.starts_with('}') && label.is_none() {
             self.pos += 1;
             self.output.push(Segment::Capture(Capture {
                 pos,
+                label,
                 rule: CaptureRule::Implicit,
+                span: self.span(start..self.pos),
             }));
             return Ok(());
         } else if source.starts_with(':') {
             self.pos += 1;
-        } else if let Some(next) = source.chars().next() {
-            return Err(format!("Unexpected character '{}' in format string", next));
+        } else if let Some(label) = label {
+            return Err(self.error(start..self.pos, format!("Expected an inline type after '{}'", label)));
         } else {
-            return Err(format!("Unexpected end of format string"));
+            return Err(self.unexpected(start));
         }
 
-        // Extract the second field: a reference to the argument describing this capture's pattern and type.
+        // Extract the second field: a reference to the argument describing this capture's pattern and type,
+        // or the pattern and type themselves.
         // This will be one of:
         //   - Implicit (""), meaning the argument is chosen by its position
         //   - Positional ("2"), meaning the argument at the given position will be used, e.g. 2
         //   - Named ("word"), meaning the argument with the given label will be used, e.g. "word = ..."
+        //     If there is no such argument, the name is taken as an inline type instead.
+        //   - Inline ("Hex<u8>"), meaning an inline type with its default regex, or an inline regex if
+        //     followed by one, e.g. "String:/[a-z]+/"
         let source = self.remainder();
-        let end_of_rule = source
-            .find(|ch: char| ch != '_' && !ch.is_ascii_alphanumeric())
-            .ok_or_else(|| String::from("Unmatched '{' in format string"))?;
-        let rule = &source[..end_of_rule];
-        let rule = if rule.starts_with(|ch: char| ch == '_' || ch.is_ascii_alphabetic()) {
-            CaptureRule::Named(rule.into())
-        } else if rule.is_empty() {
-            CaptureRule::Implicit
-        } else if let Ok(num) = rule.parse() {
-            CaptureRule::Positional(num)
+        let end_of_rule = if source.starts_with('/') {
+            0
         } else {
-            return Err(format!("Invalid rule: '{}'", rule));
+            find_end_of_type(source).ok_or_else(|| unmatched(self))?
         };
+        let rule_range = self.pos..self.pos + end_of_rule;
+        let rule = source[..end_of_rule].trim().to_string();
+        let rule = rule.as_str();
         self.pos += end_of_rule;
+        let regex = if self.remainder().starts_with(':') {
+            self.pos += 1;
+            Some(self.parse_inline_regex()?)
+        } else if self.remainder().starts_with('/') && rule.is_empty() {
+            Some(self.parse_inline_regex()?)
+        } else {
+            None
+        };
+
+        let is_name = rule.starts_with(|ch: char| ch == '_' || ch.is_ascii_alphabetic())
+            && rule.chars().all(|ch| ch == '_' || ch.is_ascii_alphanumeric());
+        let inline_type = |parser: &Self| {
+            parse_inline_type(rule, parser.span(rule_range.clone()))
+                .map_err(|message| parser.error(rule_range.clone(), message))
+        };
+        let rule = match regex {
+            Some((regex, flags)) => {
+                // A missing or `_` type is the null type, as in `"regex" as _`.
+                match rule {
+                    "" | "_" => CaptureRule::Inline(Rule::Null { regex, flags }),
+                    _ => CaptureRule::Inline(Rule::Custom { regex, typ: inline_type(self)?, flags }),
+                }
+            }
+            None if label.is_some() => CaptureRule::Inline(Rule::Default { typ: inline_type(self)?, flags: Flags::default() }),
+            None if rule.is_empty() => CaptureRule::Implicit,
+            None if is_name => CaptureRule::Named(rule.into()),
+            None => if let Ok(num) = rule.parse() {
+                CaptureRule::Positional(num)
+            } else {
+                CaptureRule::Inline(Rule::Default { typ: inline_type(self)?, flags: Flags::default() })
+            }
+        };
 
         // Ensure we're at the end of the capture.
-        let source = self.remainder();
-        if source.starts_with('}') {
+        if self.remainder().starts_with('}') {
             self.pos += 1;
             self.output.push(Segment::Capture(Capture {
                 pos,
+                label,
                 rule,
+                span: self.span(start..self.pos),
             }));
-            return Ok(());
-        } else if let Some(next) = source.chars().next() {
-            return Err(format!("Unexpected character '{}' in format string", next));
+            Ok(())
         } else {
-            return Err(format!("Unexpected end of format string"));
+            Err(self.unexpected(start))
+        }
+    }
+    /// Parses an inline regex written like `/[a-z]+/i`, with optional flags
+    /// after the closing slash. A slash within the regex is escaped as `\/`.
+    fn parse_inline_regex(&mut self) -> Result<(Box<syn::Expr>, Flags), FormatError> {
+        let start = self.pos;
+        let source = self.remainder();
+        let body = source.strip_prefix('/')
+            .ok_or_else(|| self.unexpected(start))?;
+        let mut regex = String::new();
+        let mut chars = body.char_indices();
+        let end_of_regex = loop {
+            match chars.next() {
+                Some((_, '\\')) => match chars.next() {
+                    Some((_, '/')) => regex.push('/'),
+                    Some((_, ch)) => {
+                        regex.push('\\');
+                        regex.push(ch);
+                    }
+                    None => break None,
+                },
+                Some((idx, '/')) => break Some(idx),
+                Some((_, ch)) => regex.push(ch),
+                None => break None,
+            }
+        }.ok_or_else(|| self.error(start..start + 1, "Unterminated inline regex in format string"))?;
+        let rest = &body[end_of_regex + 1..];
+        let end_of_flags = rest.find(|ch: char| ch != '-' && !ch.is_ascii_alphabetic()).unwrap_or(rest.len());
+        let mut bad_flag = None;
+        let flags = parse_flag_letters(&rest[..end_of_flags], |ch| {
+            bad_flag.get_or_insert(ch);
+        });
+        let end = start + 1 + end_of_regex + 1 + end_of_flags;
+        if let Some(ch) = bad_flag {
+            return Err(self.error(start..end, format!("Unrecognized flag '{}' in inline regex", ch)));
         }
+        self.pos = end;
+        let regex = Box::new(syn::Expr::Lit(syn::ExprLit {
+            attrs: vec![],
+            lit: syn::LitStr::new(&regex, self.span(start..end)).into(),
+        }));
+        Ok((regex, flags))
     }
 }
+
+/// Returns the span of the bytes `range` of the value of `lit`.
+///
+/// The span of the whole literal is returned instead if the bytes can't be
+/// located, either because the literal has escape sequences (so that its value
+/// and source text differ) or because the compiler doesn't support subspans.
+fn value_subspan(lit: &syn::LitStr, range: Range<usize>) -> Span {
+    let token = lit.token();
+    let source = token.to_string();
+    let offset = if source.starts_with('r') {
+        source.find('"').map(|idx| idx + 1)
+    } else if !source.contains('\\') {
+        Some(1)
+    } else {
+        None
+    };
+    offset
+        .and_then(|offset| token.subspan(offset + range.start..offset + range.end))
+        .unwrap_or_else(|| lit.span())
+}
+
+/// Returns the length of the type at the start of `source`, which ends at a
+/// `}` or a single `:` outside of any brackets.
+fn find_end_of_type(source: &str) -> Option<usize> {
+    let bytes = source.as_bytes();
+    let mut depth = 0_usize;
+    let mut idx = 0;
+    while let Some(&ch) = bytes.get(idx) {
+        match ch {
+            b'<' | b'(' | b'[' => depth += 1,
+            // The `>` of `->` doesn't close a bracket.
+            b'>' if idx > 0 && bytes[idx - 1] == b'-' => (),
+            b'>' | b')' | b']' => depth = depth.saturating_sub(1),
+            b':' if bytes.get(idx + 1) == Some(&b':') => idx += 1,
+            b':' | b'}' if depth == 0 => return Some(idx),
+            _ => (),
+        }
+        idx += 1;
+    }
+    None
+}
+
+/// Parses a type written inside the format string, giving its tokens `span`
+/// so that errors about the type point into the format string.
+fn parse_inline_type(text: &str, span: Span) -> Result<Box<syn::Type>, String> {
+    let invalid = || format!("Invalid inline type: '{}'", text);
+    let tokens: TokenStream2 = text.parse().map_err(|_| invalid())?;
+    syn::parse2(respan(tokens, span))
+        .map(Box::new)
+        .map_err(|_| invalid())
+}
+
+/// Sets the span of every token in `tokens` to `span`.
+fn respan(tokens: TokenStream2, span: Span) -> TokenStream2 {
+    tokens.into_iter().map(|mut token| {
+        if let TokenTree::Group(group) = &token {
+            let mut respanned = Group::new(group.delimiter(), respan(group.stream(), span));
+            respanned.set_span(span);
+            token = respanned.into();
+        } else {
+            token.set_span(span);
+        }
+        token
+    }).collect()
+}
@@ -0,0 +1,6 @@
+//! Support code for the `rescan` command-line tool.
+
+pub mod args;
+pub mod filter;
+pub mod output;
+pub mod pattern;
@@ -0,0 +1,193 @@
+//! Command-line argument parsing for the `rescan` tool.
+
+use crate::cli::output::Format;
+
+pub const USAGE: &str = "\
+Usage: rescan [OPTIONS] <FORMAT> [FILE]...
+       rescan --filter [FILTER OPTIONS] <FORMAT> [FILE]...
+
+Scans each line of the given files (or standard input) according to FORMAT,
+and prints the captured values as records. With --filter, prints the lines
+that match FORMAT instead.
+
+FORMAT is a format string in the syntax of `scanner!`, in which `{name}`
+captures a value, `{}` captures a value named by its index, and `{_}` skips a
+whitespace-delimited field. A capture may give its type and regex inline, as in
+`{port:u16}`, `{:String:/[a-z]+/i}` or `{_:/ +/}`. Use `{{` and `}}` for literal
+braces. Each line must match the format entirely.
+
+Options:
+  -t, --type NAME=TYPE     Scan capture NAME as TYPE (default: its inline
+                           type, or String). TYPE is one of String, char,
+                           bool, f32, f64, Decimal, an integer type such as
+                           u16, or Hex<T>, Octal<T> or Binary<T> of an integer
+                           type T, or Quoted<String>
+  -r, --regex NAME=REGEX   Match capture NAME with REGEX instead of the
+                           inline regex or the default regex of its type
+  -o, --output FORMAT      Output format: jsonl (default), csv or tsv
+      --strict             Stop at the first line that doesn't match (default)
+      --skip-invalid       Report lines that don't match and continue
+  -w, --where EXPR         Only keep records where EXPR holds. EXPR compares a
+                           capture with a value, like `status>=500`, using one
+                           of ==, !=, <, <=, > or >=. May be repeated. A record
+                           whose capture can't be compared with the value is
+                           reported as an invalid line, and is never selected
+                           by --filter
+  -h, --help               Print this help
+
+Filter options:
+  -f, --filter             Print matching lines instead of records
+  -v, --invert             Print the lines that don't match instead
+  -c, --count              Print only the number of lines selected
+      --only NAME          Print only the value of capture NAME
+
+Exit status is 0 if every line matched, 1 if any line didn't match, and 2 if
+an error occurred. With --filter, it is 0 if any line was selected, 1 if none
+was, and 2 if an error occurred.
+";
+
+/// What to do with a line that doesn't match the format.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum InvalidPolicy {
+    /// Stop processing.
+    Strict,
+    /// Report the line and continue with the next one.
+    Skip,
+}
+
+/// Options specific to `--filter`.
+#[derive(Debug, Default)]
+pub struct FilterOptions {
+    pub invert: bool,
+    pub count: bool,
+    pub only: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct Options {
+    pub format: String,
+    pub files: Vec<String>,
+    pub types: Vec<(String, String)>,
+    pub regexes: Vec<(String, String)>,
+    pub predicates: Vec<String>,
+    pub output: Format,
+    pub on_invalid: InvalidPolicy,
+    /// Set when running in `--filter` mode.
+    pub filter: Option<FilterOptions>,
+}
+
+#[derive(Debug)]
+pub enum Command {
+    Help,
+    Scan(Options),
+}
+
+/// Parses the command-line arguments, excluding the program name.
+pub fn parse_args(args: impl IntoIterator<Item = String>) -> Result<Command, String> {
+    let mut args = args.into_iter();
+    let mut positional = vec![];
+    let mut types = vec![];
+    let mut regexes = vec![];
+    let mut output = Format::JsonLines;
+    let mut on_invalid = InvalidPolicy::Strict;
+    let mut predicates = vec![];
+    let mut filter = false;
+    let mut filter_options = FilterOptions::default();
+    let mut filter_option_used = None;
+
+    while let Some(arg) = args.next() {
+        // Split `--option=value` into its parts.
+        let (option, inline_value) = match arg.split_once('=') {
+            Some((option, value)) if arg.starts_with("--") => (option.to_string(), Some(value.to_string())),
+            _ => (arg.clone(), None),
+        };
+        let mut value = || {
+            inline_value.clone()
+                .or_else(|| args.next())
+                .ok_or_else(|| format!("missing value for option `{}`", option))
+        };
+        match option.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-t" | "--type" => types.push(parse_assignment(&value()?)?),
+            "-r" | "--regex" => regexes.push(parse_assignment(&value()?)?),
+            "-o" | "--output" => {
+                let name = value()?;
+                output = Format::from_name(&name).ok_or_else(|| format!("unknown output format `{}`", name))?;
+            }
+            "--strict" => on_invalid = InvalidPolicy::Strict,
+            "--skip-invalid" => on_invalid = InvalidPolicy::Skip,
+            "-w" | "--where" => predicates.push(value()?),
+            "-f" | "--filter" => filter = true,
+            "-v" | "--invert" => {
+                filter_options.invert = true;
+                filter_option_used = Some(option);
+            }
+            "-c" | "--count" => {
+                filter_options.count = true;
+                filter_option_used = Some(option);
+            }
+            "--only" => {
+                filter_options.only = Some(value()?);
+                filter_option_used = Some(option);
+            }
+            "--" => {
+                positional.extend(args.by_ref());
+            }
+            _ if option.starts_with('-') && option != "-" => return Err(format!("unknown option `{}`", option)),
+            _ => positional.push(arg),
+        }
+    }
+
+    if let (false, Some(option)) = (filter, filter_option_used) {
+        return Err(format!("option `{}` requires `--filter`", option));
+    }
+    if filter_options.invert && filter_options.only.is_some() {
+        return Err("options `--invert` and `--only` cannot be used together".into());
+    }
+
+    let mut positional = positional.into_iter();
+    let format = positional.next().ok_or("missing format string")?;
+    Ok(Command::Scan(Options {
+        format,
+        files: positional.collect(),
+        types,
+        regexes,
+        predicates,
+        output,
+        on_invalid,
+        filter: filter.then_some(filter_options),
+    }))
+}
+
+/// Parses an argument of the form `NAME=VALUE`.
+fn parse_assignment(arg: &str) -> Result<(String, String), String> {
+    arg.split_once('=')
+        .map(|(name, value)| (name.into(), value.into()))
+        .ok_or_else(|| format!("expected NAME=VALUE, found `{}`", arg))
+}
+
+#[test]
+fn parse_args_test() {
+    let args = ["{host}:{port}", "--type", "port=u16", "-r", r"host=[\w.]+", "--output=csv", "--skip-invalid", "a.log", "-"];
+    let Ok(Command::Scan(options)) = parse_args(args.map(String::from)) else { panic!() };
+    assert_eq!("{host}:{port}", options.format);
+    assert_eq!(vec![("port".to_string(), "u16".to_string())], options.types);
+    assert_eq!(vec![("host".to_string(), r"[\w.]+".to_string())], options.regexes);
+    assert_eq!(Format::Csv, options.output);
+    assert_eq!(InvalidPolicy::Skip, options.on_invalid);
+    assert_eq!(vec!["a.log", "-"], options.files);
+
+    assert!(options.filter.is_none());
+
+    let args = ["--filter", "-c", "{} {status}", "--where", "status>=500", "-w", "0=GET"];
+    let Ok(Command::Scan(options)) = parse_args(args.map(String::from)) else { panic!() };
+    assert_eq!(vec!["status>=500", "0=GET"], options.predicates);
+    assert!(options.filter.is_some_and(|filter| filter.count && !filter.invert));
+
+    assert!(matches!(parse_args(["-h".to_string()]), Ok(Command::Help)));
+    assert!(parse_args(["{}".to_string(), "--invert".to_string()]).is_err());
+    assert!(parse_args(["-f", "-v", "--only", "a", "{a}"].map(String::from)).is_err());
+    assert!(parse_args(["{}".to_string(), "--type".to_string()]).is_err());
+    assert!(parse_args(["{}".to_string(), "--bogus".to_string()]).is_err());
+    assert!(parse_args([]).is_err());
+}
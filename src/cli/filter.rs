@@ -0,0 +1,119 @@
+//! Predicates on typed captures, given with `--where`.
+
+use crate::cli::pattern::Value;
+use rescan::Decimal;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A comparison between a capture and a constant, such as `status>=500`.
+#[derive(Debug)]
+pub struct Predicate {
+    name: String,
+    op: Op,
+    operand: String,
+}
+impl Predicate {
+    /// Parses an expression of the form `NAME OP VALUE`, where `OP` is one of
+    /// `==` (or `=`), `!=`, `<`, `<=`, `>` and `>=`.
+    pub fn parse(expr: &str) -> Result<Self, String> {
+        let idx = expr.find(['=', '!', '<', '>'])
+            .ok_or_else(|| format!("missing comparison operator in `{}`", expr))?;
+        let (name, rest) = expr.split_at(idx);
+        let (op, operand) = [
+            ("==", Op::Eq), ("!=", Op::Ne), ("<=", Op::Le), (">=", Op::Ge),
+            ("=", Op::Eq), ("<", Op::Lt), (">", Op::Gt),
+        ].into_iter()
+            .find_map(|(token, op)| Some((op, rest.strip_prefix(token)?)))
+            .ok_or_else(|| format!("invalid comparison operator in `{}`", expr))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("missing capture name in `{}`", expr));
+        }
+        Ok(Self {
+            name: name.into(),
+            op,
+            operand: operand.trim().into(),
+        })
+    }
+
+    /// The name of the capture being compared.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Tests the predicate against the value of its capture.
+    ///
+    /// The operand is interpreted according to the type of the value. An error
+    /// is returned if it can't be, e.g. when comparing a number with `abc`.
+    pub fn test(&self, value: &Value) -> Result<bool, String> {
+        let ordering = compare(value, &self.operand)
+            .ok_or_else(|| format!("cannot compare `{}` with \"{}\"", self.name, self.operand))?;
+        Ok(match self.op {
+            Op::Eq => ordering == Ordering::Equal,
+            Op::Ne => ordering != Ordering::Equal,
+            Op::Lt => ordering == Ordering::Less,
+            Op::Le => ordering != Ordering::Greater,
+            Op::Gt => ordering == Ordering::Greater,
+            Op::Ge => ordering != Ordering::Less,
+        })
+    }
+}
+
+/// Compares a value with a constant of the same type, given as text.
+fn compare(value: &Value, operand: &str) -> Option<Ordering> {
+    match value {
+        Value::Str(str) => Some(str.as_str().cmp(operand)),
+        Value::Bool(bool) => Some(bool.cmp(&operand.parse().ok()?)),
+        Value::Int(int) => match operand.parse::<i128>() {
+            Ok(operand) => Some(int.cmp(&operand)),
+            Err(_) => (*int as f64).partial_cmp(&operand.parse().ok()?),
+        },
+        Value::UInt(uint) => match operand.parse::<u128>() {
+            Ok(operand) => Some(uint.cmp(&operand)),
+            Err(_) => (*uint as f64).partial_cmp(&operand.parse().ok()?),
+        },
+        Value::Float(float) => float.partial_cmp(&operand.parse().ok()?),
+        Value::Decimal(decimal) => compare_decimals(decimal, &operand.parse().ok()?),
+    }
+}
+
+/// Compares two decimals numerically, regardless of their scales.
+fn compare_decimals(lhs: &Decimal, rhs: &Decimal) -> Option<Ordering> {
+    let scale = lhs.scale().max(rhs.scale());
+    let rescale = |decimal: &Decimal| {
+        10_i128.checked_pow(scale - decimal.scale())?.checked_mul(decimal.mantissa())
+    };
+    match (rescale(lhs), rescale(rhs)) {
+        (Some(lhs), Some(rhs)) => Some(lhs.cmp(&rhs)),
+        _ => lhs.to_f64().partial_cmp(&rhs.to_f64()),
+    }
+}
+
+#[test]
+fn predicate_test() {
+    let status = Predicate::parse("status >= 500").unwrap();
+    assert_eq!("status", status.name());
+    assert_eq!(Ok(true), status.test(&Value::UInt(503)));
+    assert_eq!(Ok(false), status.test(&Value::UInt(404)));
+    assert!(status.test(&Value::Str("500".into())).is_ok());
+    assert!(Predicate::parse("status>=abc").unwrap().test(&Value::Int(1)).is_err());
+
+    let price = Predicate::parse("price<1.5").unwrap();
+    assert_eq!(Ok(true), price.test(&Value::Decimal(Decimal::new(1499, 3))));
+    assert_eq!(Ok(false), price.test(&Value::Decimal(Decimal::new(150, 2))));
+    assert_eq!(Ok(true), price.test(&Value::Int(1)));
+
+    assert_eq!(Ok(true), Predicate::parse("user=root").unwrap().test(&Value::Str("root".into())));
+    assert_eq!(Ok(true), Predicate::parse("ok != true").unwrap().test(&Value::Bool(false)));
+    assert!(Predicate::parse("status").is_err());
+    assert!(Predicate::parse(">=5").is_err());
+}
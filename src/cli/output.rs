@@ -0,0 +1,119 @@
+//! Writers for the output formats of the `rescan` command-line tool.
+
+use crate::cli::pattern::Value;
+use std::io::{Result as IoResult, Write};
+
+/// The format of scanned records.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    /// One JSON object per line, keyed by capture name.
+    JsonLines,
+    /// Comma-separated values, with a header row.
+    Csv,
+    /// Tab-separated values, with a header row.
+    Tsv,
+}
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "json" | "jsonl" => Some(Self::JsonLines),
+            "csv" => Some(Self::Csv),
+            "tsv" => Some(Self::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Writes the header row, if this format has one.
+    pub fn write_header(self, out: &mut dyn Write, names: &[String]) -> IoResult<()> {
+        match self {
+            Self::JsonLines => Ok(()),
+            Self::Csv | Self::Tsv => {
+                let fields: Vec<_> = names.iter().map(|name| self.escape(name)).collect();
+                writeln!(out, "{}", fields.join(self.separator()))
+            }
+        }
+    }
+
+    /// Writes a single record.
+    pub fn write_record(self, out: &mut dyn Write, names: &[String], values: &[Value]) -> IoResult<()> {
+        match self {
+            Self::JsonLines => {
+                let fields: Vec<_> = names.iter().zip(values)
+                    .map(|(name, value)| format!("{}:{}", json_string(name), json_value(value)))
+                    .collect();
+                writeln!(out, "{{{}}}", fields.join(","))
+            }
+            Self::Csv | Self::Tsv => {
+                let fields: Vec<_> = values.iter().map(|value| self.escape(&value.to_string())).collect();
+                writeln!(out, "{}", fields.join(self.separator()))
+            }
+        }
+    }
+
+    fn separator(self) -> &'static str {
+        match self {
+            Self::Csv => ",",
+            _ => "\t",
+        }
+    }
+
+    /// Escapes a field of a CSV or TSV row.
+    ///
+    /// CSV fields are quoted when necessary, as described by RFC 4180. TSV
+    /// fields can't be quoted, so tabs, newlines and backslashes are written
+    /// as backslash escapes instead.
+    fn escape(self, field: &str) -> String {
+        match self {
+            Self::Csv if field.contains([',', '"', '\n', '\r']) => {
+                format!("\"{}\"", field.replace('"', "\"\""))
+            }
+            Self::Tsv => {
+                field.replace('\\', "\\\\").replace('\t', "\\t").replace('\n', "\\n").replace('\r', "\\r")
+            }
+            _ => field.into(),
+        }
+    }
+}
+
+/// Formats a value as JSON. Non-finite floats, which JSON can't represent,
+/// are written as `null`.
+fn json_value(value: &Value) -> String {
+    match value {
+        Value::Str(str) => json_string(str),
+        Value::Float(float) if !float.is_finite() => "null".into(),
+        _ => value.to_string(),
+    }
+}
+
+fn json_string(str: &str) -> String {
+    let mut result = String::with_capacity(str.len() + 2);
+    result.push('"');
+    for ch in str.chars() {
+        match ch {
+            '"' => result.push_str("\\\""),
+            '\\' => result.push_str("\\\\"),
+            '\n' => result.push_str("\\n"),
+            '\r' => result.push_str("\\r"),
+            '\t' => result.push_str("\\t"),
+            ch if ch.is_control() => result.push_str(&format!("\\u{:04x}", ch as u32)),
+            ch => result.push(ch),
+        }
+    }
+    result.push('"');
+    result
+}
+
+#[test]
+fn write_record_test() {
+    let names = ["name".to_string(), "n".to_string(), "x".to_string()];
+    let values = [Value::Str("a \"b\",\tc".into()), Value::Int(-3), Value::Float(f64::NAN)];
+    let write = |format: Format| {
+        let mut out = vec![];
+        format.write_header(&mut out, &names).unwrap();
+        format.write_record(&mut out, &names, &values).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+    assert_eq!("{\"name\":\"a \\\"b\\\",\\tc\",\"n\":-3,\"x\":null}\n", write(Format::JsonLines));
+    assert_eq!("name,n,x\n\"a \"\"b\"\",\tc\",-3,NaN\n", write(Format::Csv));
+    assert_eq!("name\tn\tx\na \"b\",\\tc\t-3\tNaN\n", write(Format::Tsv));
+}
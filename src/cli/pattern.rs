@@ -0,0 +1,431 @@
+//! Scanners built at runtime from a format string given on the command line.
+//!
+//! Format strings use the capture syntax of `scanner!`, and each capture is
+//! scanned by a `Scanner` that owns the capture's regex, so lines are matched
+//! by the library itself.
+
+use regex::Regex;
+use rescan::error::ScanError;
+use rescan::{scanner, Binary, Decimal, DefaultScan, Error, Hex, Octal, Quoted, Scan, SingleQuotes};
+use std::io::BufRead;
+
+/// A typed value extracted from a capture.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Str(String),
+    Int(i128),
+    UInt(u128),
+    Float(f64),
+    Bool(bool),
+    Decimal(Decimal),
+}
+
+/// Scans a capture from the start of a reader.
+type ScanFn = dyn Fn(&mut dyn BufRead) -> Result<Value, Error>;
+
+/// Formats a value as text, without any quoting.
+impl std::fmt::Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Str(str) => str.fmt(f),
+            Self::Int(int) => int.fmt(f),
+            Self::UInt(uint) => uint.fmt(f),
+            Self::Float(float) => float.fmt(f),
+            Self::Bool(bool) => bool.fmt(f),
+            Self::Decimal(decimal) => decimal.fmt(f),
+        }
+    }
+}
+
+/// A capture type that can be named on the command line.
+pub struct Kind {
+    regex: &'static str,
+    /// Builds a scanner for a capture of this kind that matches a regex.
+    build: Box<dyn Fn(String) -> Box<ScanFn>>,
+}
+impl Kind {
+    /// Looks up a capture type by the name of its Rust type, e.g. `u16` or
+    /// `Hex<u32>`.
+    pub fn lookup(name: &str) -> Option<Self> {
+        let name: String = name.chars().filter(|ch| !ch.is_whitespace()).collect();
+        Some(match name.as_str() {
+            "String" => default_kind::<String>(Value::Str),
+            "char" => default_kind::<char>(|ch| Value::Str(ch.into())),
+            "bool" => default_kind::<bool>(Value::Bool),
+            "f32" => kind::<f32>(Self::FLOAT_REGEX, |val| Value::Float(val.into())),
+            "f64" => kind::<f64>(Self::FLOAT_REGEX, Value::Float),
+            "Decimal" => default_kind::<Decimal>(Value::Decimal),
+            "Quoted<String>" => default_kind::<Quoted<String>>(Value::Str),
+            "Quoted<String,SingleQuotes>" => default_kind::<Quoted<String, SingleQuotes>>(Value::Str),
+            _ => return int_kind(&name),
+        })
+    }
+
+    /// The default kind of a capture.
+    pub fn string() -> Self {
+        default_kind::<String>(Value::Str)
+    }
+
+    /// Regex for floating-point numbers, which have no default regex.
+    const FLOAT_REGEX: &'static str = r"[+-]?(?:[0-9]+(?:\.[0-9]*)?|\.[0-9]+)(?:[eE][+-]?[0-9]+)?|[+-]?(?:inf|NaN)";
+}
+
+fn kind<T>(regex: &'static str, to_value: fn(T::Output) -> Value) -> Kind
+where
+    T: Scan + 'static,
+    T::Output: 'static,
+    T::Error: 'static,
+{
+    Kind {
+        regex,
+        build: Box::new(move |regex| {
+            let scanner = scanner!("{}", regex as T);
+            Box::new(move |reader| scanner.scan(reader).map(to_value))
+        }),
+    }
+}
+
+fn default_kind<T>(to_value: fn(T::Output) -> Value) -> Kind
+where
+    T: DefaultScan + 'static,
+    T::Output: 'static,
+    T::Error: 'static,
+{
+    kind::<T>(T::DEFAULT_REGEX, to_value)
+}
+
+/// Looks up an integer type, optionally inside a radix adaptor such as
+/// `Hex<u32>`.
+fn int_kind(name: &str) -> Option<Kind> {
+    macro_rules! ints {
+        ($adaptor:tt, $name:expr) => {
+            ints!(@ $adaptor, $name; Int: i8, i16, i32, i64, i128, isize; UInt: u8, u16, u32, u64, u128, usize)
+        };
+        (@ $adaptor:tt, $name:expr; $($variant:ident: $($int:ident),*);*) => {
+            match $name {
+                $($(stringify!($int) => Some(ints!(@kind $adaptor $int, $variant)),)*)*
+                _ => None,
+            }
+        };
+        (@kind [] $int:ident, $variant:ident) => {
+            default_kind::<$int>(|val| Value::$variant(val as _))
+        };
+        (@kind [$adaptor:ident] $int:ident, $variant:ident) => {
+            default_kind::<$adaptor<$int>>(|val| Value::$variant(val as _))
+        };
+    }
+
+    let inner = |adaptor: &str| name.strip_prefix(adaptor)?.strip_prefix('<')?.strip_suffix('>');
+    if let Some(int) = inner("Hex") {
+        ints!([Hex], int)
+    } else if let Some(int) = inner("Octal") {
+        ints!([Octal], int)
+    } else if let Some(int) = inner("Binary") {
+        ints!([Binary], int)
+    } else {
+        ints!([], name)
+    }
+}
+/// A capture in a parsed format string.
+#[derive(Debug, Default, PartialEq)]
+pub struct Capture {
+    /// The name of the capture, or `None` for a non-capturing `{_}`.
+    pub name: Option<String>,
+    /// The inline type of the capture, as in `{port:u16}`.
+    pub typ: Option<String>,
+    /// The inline regex of the capture, as in `{:/[a-z]+/i}`, with its flags
+    /// given in a `(?flags)` prefix.
+    pub regex: Option<String>,
+}
+
+/// A piece of a parsed format string.
+#[derive(Debug, PartialEq)]
+pub enum Piece {
+    Literal(String),
+    Capture(Capture),
+}
+
+/// Splits a format string into literals and captures.
+///
+/// Captures use the syntax of `scanner!`: `{name}` is a capture with the given
+/// name, `{}` is named by its index among the captures, and `{_}` matches a
+/// field without capturing it. Any of these may be followed by an inline type
+/// and regex, as in `{port:u16}`, `{:String:/[a-z]+/i}` or `{_:/ +/}`. Braces
+/// are escaped by doubling them.
+pub fn parse_format(format: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut literal = String::new();
+    let mut captures = 0;
+    let mut rest = format;
+    while let Some(ch) = rest.chars().next() {
+        rest = &rest[ch.len_utf8()..];
+        match ch {
+            '{' if rest.starts_with('{') => {
+                rest = &rest[1..];
+                literal.push('{');
+            }
+            '}' if rest.starts_with('}') => {
+                rest = &rest[1..];
+                literal.push('}');
+            }
+            '}' => return Err("unmatched '}' in format string".into()),
+            '{' => {
+                let (capture, after) = parse_capture(rest, captures)?;
+                rest = after;
+                if !literal.is_empty() {
+                    pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                }
+                captures += capture.name.is_some() as usize;
+                pieces.push(Piece::Capture(capture));
+            }
+            _ => literal.push(ch),
+        }
+    }
+    if !literal.is_empty() {
+        pieces.push(Piece::Literal(literal));
+    }
+    Ok(pieces)
+}
+
+/// Parses a capture following its opening brace, returning it along with the
+/// rest of the format string. An unnamed capture is named `index`.
+fn parse_capture(spec: &str, index: usize) -> Result<(Capture, &str), String> {
+    let end = spec.find(|ch: char| ch != '_' && !ch.is_alphanumeric()).unwrap_or(spec.len());
+    let (name, mut rest) = spec.split_at(end);
+    let mut capture = Capture {
+        name: match name {
+            "_" => None,
+            "" => Some(index.to_string()),
+            _ => Some(name.into()),
+        },
+        ..Capture::default()
+    };
+    if let Some(after) = rest.strip_prefix(':') {
+        rest = after;
+        if !rest.starts_with('/') {
+            let end = rest.find([':', '}']).ok_or("unmatched '{' in format string")?;
+            let typ = rest[..end].trim();
+            if typ.is_empty() {
+                return Err(format!("missing type after `{{{}:`", name));
+            }
+            capture.typ = Some(typ.into());
+            rest = &rest[end..];
+            if let Some(after) = rest.strip_prefix(':') {
+                rest = after;
+                if !rest.starts_with('/') {
+                    return Err(format!("expected a regex between slashes after `{{{}:{}:`", name, typ));
+                }
+            }
+        }
+        if let Some(after) = rest.strip_prefix('/') {
+            let (regex, after) = parse_regex(after)?;
+            capture.regex = Some(regex);
+            rest = after;
+        }
+    }
+    match rest.strip_prefix('}') {
+        Some(rest) => Ok((capture, rest)),
+        None => match rest.chars().next() {
+            Some(ch) => Err(format!("unexpected character '{}' in capture", ch)),
+            None => Err("unmatched '{' in format string".into()),
+        },
+    }
+}
+
+/// Parses an inline regex following its opening slash, along with its flags,
+/// which are prepended to it as `(?flags)`. A slash within the regex is
+/// written `\/`.
+fn parse_regex(spec: &str) -> Result<(String, &str), String> {
+    let mut regex = String::new();
+    let mut chars = spec.char_indices();
+    let end = loop {
+        match chars.next() {
+            Some((_, '\\')) => match chars.next() {
+                Some((_, '/')) => regex.push('/'),
+                Some((_, ch)) => {
+                    regex.push('\\');
+                    regex.push(ch);
+                }
+                None => break None,
+            },
+            Some((idx, '/')) => break Some(idx),
+            Some((_, ch)) => regex.push(ch),
+            None => break None,
+        }
+    }.ok_or("unterminated regex in format string")?;
+
+    let rest = &spec[end + 1..];
+    let len = rest.find(|ch: char| !"imsUxu-".contains(ch)).unwrap_or(rest.len());
+    if len > 0 {
+        regex = format!("(?{}){}", &rest[..len], regex);
+    }
+    Ok((regex, &rest[len..]))
+}
+
+enum Segment {
+    Literal(String),
+    Skip(Box<ScanFn>),
+    Capture(Box<ScanFn>),
+}
+
+/// A scanner for a format string whose captures are typed at runtime.
+pub struct Pattern {
+    segments: Vec<Segment>,
+    names: Vec<String>,
+}
+impl Pattern {
+    /// Regex used for non-capturing `{_}` fields.
+    const SKIP_REGEX: &'static str = r"\S+";
+
+    /// Builds a pattern from a format string, with the given types and regexes
+    /// for its named captures, which take precedence over inline ones.
+    /// Captures without a type are scanned as `String`s.
+    pub fn new(format: &str, types: &[(String, String)], regexes: &[(String, String)]) -> Result<Self, String> {
+        let pieces = parse_format(format)?;
+        let names: Vec<String> = pieces.iter()
+            .filter_map(|piece| match piece {
+                Piece::Capture(capture) => capture.name.clone(),
+                Piece::Literal(_) => None,
+            })
+            .collect();
+        for (name, _) in types.iter().chain(regexes) {
+            if !names.contains(name) {
+                return Err(format!("there is no capture named `{}`", name));
+            }
+        }
+        let mut seen = vec![];
+        for name in &names {
+            if seen.contains(&name) {
+                return Err(format!("duplicate capture name `{}`", name));
+            }
+            seen.push(name);
+        }
+
+        let segments = pieces.into_iter().map(|piece| Ok(match piece {
+            Piece::Literal(lit) => Segment::Literal(lit),
+            Piece::Capture(capture) => {
+                let name = capture.name.as_deref();
+                let kind = match option_for(types, name).or(capture.typ.as_deref()) {
+                    Some(typ) => Kind::lookup(typ).ok_or_else(|| format!("unsupported type `{}`", typ))?,
+                    None => Kind::string(),
+                };
+                let regex = match option_for(regexes, name).or(capture.regex.as_deref()) {
+                    Some(regex) => regex,
+                    None if name.is_none() && capture.typ.is_none() => Self::SKIP_REGEX,
+                    None => kind.regex,
+                };
+                let scan = build(&kind, regex)?;
+                match name {
+                    Some(_) => Segment::Capture(scan),
+                    None => Segment::Skip(scan),
+                }
+            }
+        })).collect::<Result<_, String>>()?;
+
+        Ok(Self { segments, names })
+    }
+
+    /// The names of the captures, in order.
+    pub fn names(&self) -> &[String] {
+        &self.names
+    }
+
+    /// The index of the capture with the given name.
+    pub fn index_of(&self, name: &str) -> Option<usize> {
+        self.names.iter().position(|capture| capture == name)
+    }
+
+    /// Scans a whole line, returning the value of each capture.
+    pub fn scan(&self, mut line: &[u8]) -> Result<Vec<Value>, Error> {
+        let mut values = vec![];
+        for segment in &self.segments {
+            match segment {
+                Segment::Literal(lit) => {
+                    line = line.strip_prefix(lit.as_bytes())
+                        .ok_or_else(|| ScanError::ScanLiteralError(lit.clone()))?;
+                }
+                Segment::Skip(scan) => {
+                    scan(&mut line)?;
+                }
+                Segment::Capture(scan) => values.push(scan(&mut line)?),
+            }
+        }
+        if !line.is_empty() {
+            let rest = String::from_utf8_lossy(line).into_owned();
+            return Err(Error::from_parse_error(TrailingInput(rest)));
+        }
+        Ok(values)
+    }
+}
+
+/// The value given with `--type` or `--regex` for the capture `name`, if any.
+fn option_for<'a>(options: &'a [(String, String)], name: Option<&str>) -> Option<&'a str> {
+    let name = name?;
+    options.iter().rev()
+        .find(|(key, _)| key == name)
+        .map(|(_, value)| value.as_str())
+}
+
+/// Builds a scanner for a capture of the given kind, checking its regex first
+/// so that an invalid one is reported before any line is scanned.
+fn build(kind: &Kind, regex: &str) -> Result<Box<ScanFn>, String> {
+    Regex::new(regex).map_err(|err| err.to_string())?;
+    Ok((kind.build)(regex.into()))
+}
+
+/// Error returned when a line has input left over after its pattern.
+#[derive(Debug)]
+struct TrailingInput(String);
+impl std::fmt::Display for TrailingInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "unexpected trailing input \"{}\"", self.0)
+    }
+}
+impl std::error::Error for TrailingInput {}
+
+#[test]
+fn parse_format_test() {
+    let capture = |name: Option<&str>| Piece::Capture(Capture { name: name.map(Into::into), ..Capture::default() });
+    assert_eq!(Ok(vec![
+        capture(Some("host")),
+        Piece::Literal(":".into()),
+        capture(Some("1")),
+        Piece::Literal(" {".into()),
+        capture(None),
+        Piece::Literal("}".into()),
+    ]), parse_format("{host}:{} {{{_}}}"));
+
+    assert_eq!(Ok(vec![
+        Piece::Capture(Capture { name: Some("0".into()), typ: Some("Hex<u8>".into()), regex: None }),
+        Piece::Capture(Capture { name: Some("id".into()), typ: Some("u16".into()), regex: Some("[0-9]{1,3}".into()) }),
+        Piece::Capture(Capture { name: None, typ: None, regex: Some(r"(?i-u)a/b\d".into()) }),
+    ]), parse_format(r"{:Hex<u8>}{id:u16:/[0-9]{1,3}/}{_:/a\/b\d/i-u}"));
+
+    assert!(parse_format("{host").is_err());
+    assert!(parse_format("a}b").is_err());
+    assert!(parse_format("{:/abc}").is_err());
+    assert!(parse_format("{a:u8:abc}").is_err());
+    assert!(parse_format("{a:}").is_err());
+}
+
+#[test]
+fn pattern_test() {
+    let scan = |pattern: &Pattern, line: &str| pattern.scan(line.as_bytes()).map_err(|err| err.to_string());
+
+    let types = [("port".into(), "u16".into()), ("2".into(), "Hex<u8>".into())];
+    let regexes = [("host".into(), r"[\w.]+".into())];
+    let pattern = Pattern::new("{host}:{port} {} {_}", &types, &regexes).unwrap();
+    assert_eq!(["host", "port", "2"], pattern.names());
+    assert_eq!(Ok(vec![Value::Str("example.com".into()), Value::UInt(8080), Value::UInt(0xff)]),
+        scan(&pattern, "example.com:8080 ff x"));
+    assert!(scan(&pattern, "example.com:80800 ff x").is_err());
+    assert_eq!(Err("unexpected trailing input \" y\"".into()), scan(&pattern, "example.com:8080 ff x y"));
+    assert!(Pattern::new("{a}", &[("b".into(), "u8".into())], &[]).is_err());
+    assert!(Pattern::new("{a}", &[], &[("a".into(), "[a-z".into())]).is_err());
+
+    let pattern = Pattern::new(r"{level:String:/[a-z]+/i}{_:/ +/}{code:i32} {:Hex<u8>}", &[("code".into(), "u8".into())], &[]).unwrap();
+    assert_eq!(["level", "code", "2"], pattern.names());
+    assert_eq!(Ok(vec![Value::Str("WARN".into()), Value::UInt(7), Value::UInt(0x1f)]), scan(&pattern, "WARN   7 1f"));
+    assert!(scan(&pattern, "WARN 300 1f").is_err());
+    assert!(Pattern::new("{:Vec<u8>}", &[], &[]).is_err());
+}
@@ -0,0 +1,144 @@
+use std::str::FromStr;
+
+/// A fixed-point decimal number that preserves the exact digits it was parsed
+/// from.
+///
+/// Unlike `f64`, parsing a `Decimal` never rounds: `"0.10"` is stored as the
+/// integer `10` with a scale of `2`, i.e. `10 × 10⁻²`. Both the digits and the
+/// scale are kept, so `"0.10"` and `"0.1"` compare as different values. The
+/// sign is kept as well, so `"-0"` differs from `"0"`.
+///
+/// Up to 38 significant digits are supported.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanln_from, Decimal, Error};
+/// let mut input = "-1234.50".as_bytes();
+/// let value = scanln_from!(&mut input, "{}", Decimal)?;
+/// assert_eq!(-123450, value.mantissa());
+/// assert_eq!(2, value.scale());
+/// assert_eq!("-1234.50", value.to_string());
+/// # Ok::<(), Error>(())
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Decimal {
+    mantissa: i128,
+    scale: u32,
+    negative: bool,
+}
+impl Decimal {
+    /// Creates a decimal with the value `mantissa × 10^-scale`.
+    pub fn new(mantissa: i128, scale: u32) -> Self {
+        Self { mantissa, scale, negative: mantissa < 0 }
+    }
+
+    /// Returns the digits of this number as an integer, ignoring the
+    /// decimal point.
+    pub fn mantissa(&self) -> i128 {
+        self.mantissa
+    }
+
+    /// Returns the number of digits after the decimal point.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    /// Returns `true` if this number was written with a minus sign, including
+    /// a negative zero such as `"-0.0"`.
+    pub fn is_sign_negative(&self) -> bool {
+        self.negative
+    }
+
+    /// Returns the nearest `f64` to this number.
+    pub fn to_f64(&self) -> f64 {
+        // Formatting and reparsing avoids the double rounding of a division.
+        self.to_string().parse().unwrap_or(f64::NAN)
+    }
+}
+impl FromStr for Decimal {
+    type Err = ParseDecimalError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (negative, unsigned) = match s.as_bytes().first() {
+            Some(b'-') => (true, &s[1..]),
+            Some(b'+') => (false, &s[1..]),
+            _ => (false, s),
+        };
+        let (integral, fractional) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+        if integral.is_empty() && fractional.is_empty() {
+            return Err(ParseDecimalError { kind: DecimalErrorKind::Empty });
+        }
+
+        let mut mantissa: i128 = 0;
+        for ch in integral.chars().chain(fractional.chars()) {
+            let digit = ch.to_digit(10)
+                .ok_or(ParseDecimalError { kind: DecimalErrorKind::InvalidDigit })?;
+            mantissa = mantissa.checked_mul(10)
+                .and_then(|m| m.checked_add(digit as i128))
+                .ok_or(ParseDecimalError { kind: DecimalErrorKind::Overflow })?;
+        }
+        let scale = u32::try_from(fractional.len())
+            .map_err(|_| ParseDecimalError { kind: DecimalErrorKind::Overflow })?;
+
+        Ok(Self {
+            mantissa: if negative { -mantissa } else { mantissa },
+            scale,
+            negative,
+        })
+    }
+}
+impl std::fmt::Display for Decimal {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let digits = self.mantissa.unsigned_abs().to_string();
+        let scale = self.scale as usize;
+        let sign = if self.negative { "-" } else { "" };
+        if scale == 0 {
+            write!(f, "{}{}", sign, digits)
+        } else if digits.len() > scale {
+            let (integral, fractional) = digits.split_at(digits.len() - scale);
+            write!(f, "{}{}.{}", sign, integral, fractional)
+        } else {
+            write!(f, "{}0.{:0>width$}", sign, digits, width = scale)
+        }
+    }
+}
+
+/// Error returned when parsing a [`Decimal`] fails.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseDecimalError {
+    kind: DecimalErrorKind,
+}
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum DecimalErrorKind {
+    Empty,
+    InvalidDigit,
+    Overflow,
+}
+impl std::fmt::Display for ParseDecimalError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self.kind {
+            DecimalErrorKind::Empty => write!(f, "cannot parse decimal from empty string"),
+            DecimalErrorKind::InvalidDigit => write!(f, "invalid digit found in string"),
+            DecimalErrorKind::Overflow => write!(f, "number too large to fit in decimal"),
+        }
+    }
+}
+impl std::error::Error for ParseDecimalError {}
+
+#[test]
+fn decimal_round_trip() {
+    for text in ["0", "-0", "-0.000", "12", "-12", "0.5", "-0.05", "1234.500", "0.000", "-98765.4321"] {
+        assert_eq!(text, text.parse::<Decimal>().unwrap().to_string());
+    }
+    assert_eq!(Ok(Decimal::new(5, 1)), ".5".parse());
+    assert_eq!(Ok(Decimal::new(5, 0)), "+5.".parse());
+    let negative_zero: Decimal = "-0".parse().unwrap();
+    assert!(negative_zero.is_sign_negative());
+    assert_eq!(0, negative_zero.mantissa());
+    assert_ne!(Decimal::new(0, 0), negative_zero);
+    assert!(negative_zero.to_f64().is_sign_negative());
+    assert!("".parse::<Decimal>().is_err());
+    assert!("-.".parse::<Decimal>().is_err());
+    assert!("1.2.3".parse::<Decimal>().is_err());
+    assert!("1e5".parse::<Decimal>().is_err());
+    assert!("1000000000000000000000000000000000000000".parse::<Decimal>().is_err());
+}
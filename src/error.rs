@@ -18,11 +18,24 @@ pub enum Error {
     ScanError(ScanError),
     /// Error returned when the [`Scan::scan`](crate::Scan::scan) function fails.
     ParseError(Box<dyn StdError>),
+    /// Error returned when a nested scanner fails. The position of the outer
+    /// capture is recorded, or `None` for a non-capturing specifier.
+    NestedError {
+        position: Option<usize>,
+        error: Box<Error>,
+    },
 }
 impl Error {
     pub fn from_parse_error(error: impl StdError + 'static) -> Self {
         Self::ParseError(Box::new(error))
     }
+
+    pub fn from_nested_error(position: Option<usize>, error: Error) -> Self {
+        Self::NestedError {
+            position,
+            error: Box::new(error),
+        }
+    }
 }
 impl std::fmt::Display for Error {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -30,6 +43,12 @@ impl std::fmt::Display for Error {
             Self::RegexError(error) => error.fmt(f),
             Self::ScanError(error) => error.fmt(f),
             Self::ParseError(error) => error.fmt(f),
+            Self::NestedError { position: Some(position), error } => {
+                write!(f, "in capture {}: {}", position, error)
+            }
+            Self::NestedError { position: None, error } => {
+                write!(f, "in non-capturing specifier: {}", error)
+            }
         }
     }
 }
@@ -39,6 +58,7 @@ impl StdError for Error {
             Self::RegexError(error) => Some(error),
             Self::ScanError(error) => Some(error),
             Self::ParseError(error) => Some(error.as_ref()),
+            Self::NestedError { error, .. } => Some(error.as_ref()),
         }
     }
 }
@@ -53,10 +73,69 @@ impl From<ScanError> for Error {
     }
 }
 
+/// The result of a scan that failed partway through, returned by
+/// [`Scanner::scan_partial`](crate::Scanner::scan_partial).
+///
+/// The captures that were scanned before the failure are kept as a value of
+/// type `P`, which is the output's [`Captures::Partial`](crate::Captures)
+/// type: usually a tuple with an `Option` for each capture. The index of the
+/// format-string segment that failed (counting both literals and captures from
+/// zero) and the error are also recorded.
+///
+/// If the scanner's regexes fail to compile, no captures are kept and the
+/// failing segment is `0`.
+#[derive(Debug)]
+pub struct PartialScan<P> {
+    captures: P,
+    segment: usize,
+    error: Error,
+}
+impl<P> PartialScan<P> {
+    pub(crate) fn new(segment: usize, error: Error, captures: P) -> Self {
+        Self { captures, segment, error }
+    }
+
+    /// Returns the captures that were scanned before the failure.
+    pub fn captures(&self) -> &P {
+        &self.captures
+    }
+
+    /// Returns the captures that were scanned before the failure, discarding
+    /// the error.
+    pub fn into_captures(self) -> P {
+        self.captures
+    }
+
+    /// Returns the index of the format-string segment at which scanning failed.
+    pub fn segment(&self) -> usize {
+        self.segment
+    }
+
+    /// Returns the error that stopped the scan.
+    pub fn error(&self) -> &Error {
+        &self.error
+    }
+
+    /// Discards the captures, returning the error that stopped the scan.
+    pub fn into_error(self) -> Error {
+        self.error
+    }
+}
+impl<P> std::fmt::Display for PartialScan<P> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "in segment {}: {}", self.segment, self.error)
+    }
+}
+impl<P: std::fmt::Debug> StdError for PartialScan<P> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(&self.error)
+    }
+}
+
 /// Error type indicating either an I/O error or failure to match input with a
 /// scanning pattern.
 ///
-/// There are (currently) four cases handled by this type:
+/// There are (currently) five cases handled by this type:
 /// - `ScanIoError` signals the failure of an I/O operation. The original error
 ///   is encapsulated.
 /// - `ScanDecodeError` indicates that the byte stream contained invalid UTF-8
@@ -67,6 +146,8 @@ impl From<ScanError> for Error {
 /// - `ScanRegexError` indicates that the input did not match the regex
 ///   corresponding to a capture in the format string. The expected regex is
 ///   returned as a string.
+/// - `ScanLimitError` indicates that the input exceeded one of the scanner's
+///   [`Limits`](crate::Limits). The limit that was exceeded is returned.
 #[derive(Debug)]
 #[non_exhaustive]
 pub enum ScanError {
@@ -74,6 +155,7 @@ pub enum ScanError {
     ScanDecodeError(Utf8Error),
     ScanLiteralError(String),
     ScanRegexError(String),
+    ScanLimitError(Limit),
 }
 impl std::fmt::Display for ScanError {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
@@ -90,6 +172,9 @@ impl std::fmt::Display for ScanError {
             Self::ScanRegexError(regex) => {
                 write!(f, "input text does not match regex \"{}\"", regex)
             }
+            Self::ScanLimitError(limit) => {
+                limit.fmt(f)
+            }
         }
     }
 }
@@ -105,6 +190,29 @@ impl From<Utf8Error> for ScanError {
     }
 }
 
+/// A limit on the size of the input, as set in [`Limits`](crate::Limits).
+///
+/// Each variant holds the maximum number of bytes that was exceeded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Limit {
+    /// The maximum length of a line.
+    LineLength(usize),
+    /// The maximum length of the text matched by a single capture.
+    CaptureLength(usize),
+    /// The maximum number of bytes read by a single scan.
+    ScanLength(usize),
+}
+impl std::fmt::Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::LineLength(max) => write!(f, "line is longer than {} bytes", max),
+            Self::CaptureLength(max) => write!(f, "capture is longer than {} bytes", max),
+            Self::ScanLength(max) => write!(f, "scan reads more than {} bytes", max),
+        }
+    }
+}
+
 /// Error type resulting from invalid UTF-8 characters in a byte stream.
 ///
 /// The byte sequence containing the unexpected byte can be obtained using the
@@ -3,39 +3,67 @@
 use crate::{Scan, DefaultScan, Scanner, Error, Result};
 use crate::_rescan_internal::*;
 
-fn build_regexes() -> Result<Vec<Regex>, RegexError> {
+fn build_regexes() -> Result<Vec<ScanRegex>, RegexError> {
     [
-        Regex::new(r"[[:alpha:]]+\s[[:alpha:]]+"),
-        Regex::new(r"[[:digit:]]+\s[[:alpha:]]+"),
+        cached_regex(r"[[:alpha:]]+\s[[:alpha:]]+", RegexFlags::default()),
+        cached_regex(r"[[:digit:]]+\s[[:alpha:]]+", RegexFlags::default()),
     ].into_iter().collect()
 }
 
-fn scan(reader: &mut dyn std::io::BufRead, regexes: &[Regex]) -> Result<(String, String)> {
-    use crate::{Scan, DefaultScan, Error};
+fn scan(reader: &mut dyn ScanInput, regexes: &[ScanRegex], ctx: &mut (), partial: Option<&mut dyn PartialSink<(String, String)>>) -> Result<(String, String)> {
+    use crate::{ScanWith, Error};
 
     let lit_0 = "One might expect ";
+    let mut cap_0 = None;
     let lit_1 = " to have at least ";
+    let mut cap_1 = None;
     let lit_2 = ".";
 
-    match_literal(reader, lit_0)?;
-    let cap_0 = {
-        let str = match_regex(reader, &regexes[0])?;
-        let val = <String as Scan>::scan(str).map_err(Error::from_parse_error)?;
-        let str_len = str.len();
-        advance_from_regex(reader, str_len);
-        val
+    let failure: Option<(usize, Error)> = 'scan: {
+        if let Err(error) = match_literal(reader, lit_0) {
+            break 'scan Some((0, error.into()));
+        }
+        match scan_capture(reader, &regexes[0], |str| <String as ScanWith<()>>::scan_with(str, ctx)) {
+            Ok(val) => cap_0 = Some(val),
+            Err(error) => break 'scan Some((1, error)),
+        }
+        if let Err(error) = match_literal(reader, lit_1) {
+            break 'scan Some((2, error.into()));
+        }
+        match scan_capture(reader, &regexes[1], |str| <String as ScanWith<()>>::scan_with(str, ctx)) {
+            Ok(val) => cap_1 = Some(val),
+            Err(error) => break 'scan Some((3, error)),
+        }
+        if let Err(error) = match_literal(reader, lit_2) {
+            break 'scan Some((4, error.into()));
+        }
+        None
     };
-    match_literal(reader, lit_1)?;
-    let cap_1 = {
-        let str = match_regex(reader, &regexes[1])?;
-        let val = <String as Scan>::scan(str).map_err(Error::from_parse_error)?;
-        let str_len = str.len();
-        advance_from_regex(reader, str_len);
-        val
-    };
-    match_literal(reader, lit_2)?;
+    match (failure, cap_0, cap_1) {
+        (None, Some(cap_0), Some(cap_1)) => Ok((cap_0, cap_1)),
+        (Some((segment, error)), cap_0, cap_1) => {
+            if let Some(partial) = partial {
+                partial.keep(segment, (cap_0, cap_1));
+            }
+            Err(error)
+        }
+        _ => unreachable!(),
+    }
+}
 
-    Ok((cap_0, cap_1))
+fn sample(sampler: &mut crate::Sampler, regexes: &[ScanRegex], out: &mut String) -> Option<(String, String)> {
+    use crate::ScanWith;
+
+    out.push_str("One might expect ");
+    let start = out.len();
+    sampler.sample_regex(regexes[0].as_str(), RegexFlags::default(), out);
+    let cap_0 = <String as ScanWith<()>>::scan_with(&out[start..], &mut ()).ok();
+    out.push_str(" to have at least ");
+    let start = out.len();
+    sampler.sample_regex(regexes[1].as_str(), RegexFlags::default(), out);
+    let cap_1 = <String as ScanWith<()>>::scan_with(&out[start..], &mut ()).ok();
+    out.push('.');
+    Some((cap_0?, cap_1?))
 }
 
 #[test]
@@ -47,4 +75,7 @@ fn test_scanner() {
 
     assert_eq!("most people", sub);
     assert_eq!("4 fingers", obj);
+
+    let scanner = crate::Scanner::new(build_regexes, scan).with_sampler(sample);
+    scanner.assert_samples(&mut crate::Sampler::new(0), 10);
 }
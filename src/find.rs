@@ -0,0 +1,104 @@
+use crate::internal::SliceInput;
+use crate::{Scanner, Result};
+use std::ops::Range;
+
+/// A value found in a text by [`Scanner::find`] or [`Scanner::find_iter`],
+/// with the span of the text it was scanned from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Match<T> {
+    start: usize,
+    end: usize,
+    value: T,
+}
+impl<T> Match<T> {
+    /// Returns the byte offset of the start of the match.
+    pub fn start(&self) -> usize {
+        self.start
+    }
+
+    /// Returns the byte offset of the end of the match.
+    pub fn end(&self) -> usize {
+        self.end
+    }
+
+    /// Returns the byte range of the match.
+    pub fn range(&self) -> Range<usize> {
+        self.start..self.end
+    }
+
+    /// Returns the scanned value.
+    pub fn value(&self) -> &T {
+        &self.value
+    }
+
+    /// Consumes the match, returning the scanned value.
+    pub fn into_value(self) -> T {
+        self.value
+    }
+}
+
+/// An iterator over every non-overlapping match of a [`Scanner`] in a text.
+///
+/// This struct is created by calling [`find_iter`](Scanner::find_iter).
+pub struct FindIter<'a, T> {
+    scanner: &'a Scanner<T>,
+    text: &'a str,
+    pos: usize,
+}
+impl<T> Iterator for FindIter<'_, T> {
+    type Item = Match<T>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let found = self.scanner.find_from(self.text, self.pos)?;
+        self.pos = if found.end > found.start {
+            found.end
+        } else {
+            // Step past an empty match, so it isn't found again.
+            self.text[found.end..].chars().next().map_or(self.text.len() + 1, |ch| found.end + ch.len_utf8())
+        };
+        Some(found)
+    }
+}
+
+impl<T> Scanner<T> {
+    /// Searches `text` for the first position at which it can be scanned.
+    ///
+    /// Unlike [`scan`](Self::scan), which only matches at the start of its
+    /// input, the scan is attempted at each character of `text` in turn. The
+    /// value and span of the first successful scan are returned, or `None` if
+    /// there is none. An error is only returned if the scanner's regexes fail
+    /// to compile.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, Error};
+    /// let scanner = scanner!("user={} id={}", r"\w+" as String, u32);
+    /// let found = scanner.find("<bot> welcome user=ada id=7!")?.unwrap();
+    /// assert_eq!(&("ada".to_string(), 7), found.value());
+    /// assert_eq!(14..27, found.range());
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn find(&self, text: &str) -> Result<Option<Match<T>>> {
+        self.regexes()?;
+        Ok(self.find_from(text, 0))
+    }
+
+    /// Returns an iterator over every non-overlapping position at which
+    /// `text` can be scanned.
+    ///
+    /// The search resumes at the end of each match. See [`find`](Self::find).
+    pub fn find_iter<'a>(&'a self, text: &'a str) -> Result<FindIter<'a, T>> {
+        self.regexes()?;
+        Ok(FindIter { scanner: self, text, pos: 0 })
+    }
+
+    /// Returns the first match in `text` at or after `pos`.
+    fn find_from(&self, text: &str, pos: usize) -> Option<Match<T>> {
+        let starts = text.get(pos..)?.char_indices().map(|(offset, _)| pos + offset);
+        starts.chain(std::iter::once(text.len())).find_map(|start| {
+            let mut input = SliceInput::from_text(&text[start..]);
+            let value = self.scan_input(&mut input, &mut ()).ok()?;
+            let end = text.len() - input.remaining();
+            Some(Match { start, end, value })
+        })
+    }
+}
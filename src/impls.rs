@@ -1,5 +1,5 @@
 use crate::*;
-use std::{marker::PhantomData, str::FromStr};
+use std::{fmt, marker::PhantomData, str::FromStr};
 
 macro_rules! impl_scan_as_from_str {
     ($ty:ty) => {
@@ -50,6 +50,103 @@ impl_scan_as_from_str!(std::net::Ipv6Addr);
 impl_scan_as_from_str!(std::net::SocketAddr);
 impl_scan_as_from_str!(std::net::SocketAddrV4);
 impl_scan_as_from_str!(std::net::SocketAddrV6);
+impl_scan_as_from_str!(Decimal);
+
+macro_rules! impl_print_as_display {
+    ($($ty:ty),*) => {$(
+        impl Print for $ty {
+            fn print(value: &Self, out: &mut dyn fmt::Write) -> fmt::Result {
+                write!(out, "{}", value)
+            }
+        }
+    )*}
+}
+
+impl_print_as_display!(bool, char, String, Decimal);
+impl_print_as_display!(u8, u16, u32, u64, u128, usize);
+impl_print_as_display!(i8, i16, i32, i64, i128, isize);
+impl_print_as_display!(f32, f64);
+impl_print_as_display!(
+    std::num::NonZeroU8, std::num::NonZeroU16, std::num::NonZeroU32,
+    std::num::NonZeroU64, std::num::NonZeroU128, std::num::NonZeroUsize
+);
+impl_print_as_display!(
+    std::num::NonZeroI8, std::num::NonZeroI16, std::num::NonZeroI32,
+    std::num::NonZeroI64, std::num::NonZeroI128, std::num::NonZeroIsize
+);
+impl_print_as_display!(
+    std::net::IpAddr, std::net::Ipv4Addr, std::net::Ipv6Addr,
+    std::net::SocketAddr, std::net::SocketAddrV4, std::net::SocketAddrV6
+);
+
+// Paths that aren't valid Unicode can't be printed exactly, so they are
+// printed lossily.
+impl Print for std::ffi::OsString {
+    fn print(value: &Self, out: &mut dyn fmt::Write) -> fmt::Result {
+        out.write_str(&value.to_string_lossy())
+    }
+}
+impl Print for std::path::PathBuf {
+    fn print(value: &Self, out: &mut dyn fmt::Write) -> fmt::Result {
+        out.write_str(&value.to_string_lossy())
+    }
+}
+
+macro_rules! impl_captures_single {
+    ($($ty:ty),*) => {$(
+        impl Captures for $ty {
+            type Partial = Option<Self>;
+            fn from_whole(value: Option<Self>) -> Option<Self> {
+                value
+            }
+        }
+    )*}
+}
+
+impl_captures_single!(bool, char, String, std::ffi::OsString, std::path::PathBuf, Decimal);
+impl_captures_single!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64);
+impl_captures_single!(
+    std::num::NonZeroU8, std::num::NonZeroU16, std::num::NonZeroU32,
+    std::num::NonZeroU64, std::num::NonZeroU128, std::num::NonZeroUsize,
+    std::num::NonZeroI8, std::num::NonZeroI16, std::num::NonZeroI32,
+    std::num::NonZeroI64, std::num::NonZeroI128, std::num::NonZeroIsize
+);
+impl_captures_single!(
+    std::net::IpAddr, std::net::Ipv4Addr, std::net::Ipv6Addr,
+    std::net::SocketAddr, std::net::SocketAddrV4, std::net::SocketAddrV6
+);
+
+impl Captures for () {
+    type Partial = ();
+    fn from_whole(_value: Option<Self>) {}
+}
+
+macro_rules! impl_captures_tuple {
+    ($($name:ident),*) => {
+        impl<$($name),*> Captures for ($($name,)*) {
+            type Partial = ($(Option<$name>,)*);
+            #[allow(non_snake_case)]
+            fn from_whole(value: Option<Self>) -> Self::Partial {
+                match value {
+                    Some(($($name,)*)) => ($(Some($name),)*),
+                    None => ($(None::<$name>,)*),
+                }
+            }
+        }
+    }
+}
+
+impl_captures_tuple!(A, B);
+impl_captures_tuple!(A, B, C);
+impl_captures_tuple!(A, B, C, D);
+impl_captures_tuple!(A, B, C, D, E);
+impl_captures_tuple!(A, B, C, D, E, F);
+impl_captures_tuple!(A, B, C, D, E, F, G);
+impl_captures_tuple!(A, B, C, D, E, F, G, H);
+impl_captures_tuple!(A, B, C, D, E, F, G, H, I);
+impl_captures_tuple!(A, B, C, D, E, F, G, H, I, J);
+impl_captures_tuple!(A, B, C, D, E, F, G, H, I, J, K);
+impl_captures_tuple!(A, B, C, D, E, F, G, H, I, J, K, L);
 
 macro_rules! impl_default_scan {
     ($ty:ty, $re:expr) => {
@@ -91,6 +188,8 @@ impl_default_scan!(std::num::NonZeroI64, INT_REGEX);
 impl_default_scan!(std::num::NonZeroI128, INT_REGEX);
 impl_default_scan!(std::num::NonZeroIsize, INT_REGEX);
 
+impl_default_scan!(Decimal, r"[+-]?(?:[0-9]+(?:\.[0-9]*)?|\.[0-9]+)");
+
 /// Implementation of [`Scan`](crate::Scan) and [`DefaultScan`](crate::DefaultScan)
 /// for `T`, interpreting input as binary.
 ///
@@ -177,3 +276,149 @@ macro_rules! impl_scan_as_from_str_radix {
 
 impl_scan_as_from_str_radix!(u8, u16, u32, u64, u128, usize);
 impl_scan_as_from_str_radix!(i8, i16, i32, i64, i128, isize);
+
+macro_rules! impl_print_radix {
+    (unsigned $adaptor:ident, $fmt:literal: $($output:ty),*) => {$(
+        impl Print for $adaptor<$output> {
+            fn print(value: &$output, out: &mut dyn fmt::Write) -> fmt::Result {
+                write!(out, $fmt, value)
+            }
+        }
+    )*};
+    // Signed values are printed with a sign, rather than in two's complement.
+    (signed $adaptor:ident, $fmt:literal: $($output:ty),*) => {$(
+        impl Print for $adaptor<$output> {
+            fn print(value: &$output, out: &mut dyn fmt::Write) -> fmt::Result {
+                if *value < 0 {
+                    out.write_char('-')?;
+                }
+                write!(out, $fmt, value.unsigned_abs())
+            }
+        }
+    )*};
+}
+
+impl_print_radix!(unsigned Binary, "{:b}": u8, u16, u32, u64, u128, usize);
+impl_print_radix!(unsigned Octal, "{:o}": u8, u16, u32, u64, u128, usize);
+impl_print_radix!(unsigned Hex, "{:x}": u8, u16, u32, u64, u128, usize);
+impl_print_radix!(signed Binary, "{:b}": i8, i16, i32, i64, i128, isize);
+impl_print_radix!(signed Octal, "{:o}": i8, i16, i32, i64, i128, isize);
+impl_print_radix!(signed Hex, "{:x}": i8, i16, i32, i64, i128, isize);
+
+/// The characters used to group digits and to mark the decimal point in a
+/// [`Grouped`] number.
+///
+/// Presets are provided for the most common conventions, but the trait can be
+/// implemented to support others. The regexes must accept every string that
+/// [`Grouped`]'s `scan` can parse, using the same separators.
+pub trait Separators {
+    /// Characters that may separate groups of digits.
+    const THOUSANDS: &'static [char];
+    /// The character separating the integral and fractional parts.
+    const DECIMAL: char;
+    /// Default regex for unsigned integers.
+    const UINT_REGEX: &'static str;
+    /// Default regex for signed integers.
+    const INT_REGEX: &'static str;
+    /// Default regex for numbers with an optional fractional part.
+    const FLOAT_REGEX: &'static str;
+}
+
+/// Separators for numbers written like `1,234,567.89`.
+pub struct CommaDot;
+impl Separators for CommaDot {
+    const THOUSANDS: &'static [char] = &[','];
+    const DECIMAL: char = '.';
+    const UINT_REGEX: &'static str = r"\+?(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)";
+    const INT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)";
+    const FLOAT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:,[0-9]{3})+|[0-9]+)(?:\.[0-9]+)?";
+}
+
+/// Separators for numbers written like `1.234.567,89`.
+pub struct DotComma;
+impl Separators for DotComma {
+    const THOUSANDS: &'static [char] = &['.'];
+    const DECIMAL: char = ',';
+    const UINT_REGEX: &'static str = r"\+?(?:[0-9]{1,3}(?:\.[0-9]{3})+|[0-9]+)";
+    const INT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:\.[0-9]{3})+|[0-9]+)";
+    const FLOAT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:\.[0-9]{3})+|[0-9]+)(?:,[0-9]+)?";
+}
+
+/// Separators for numbers written like `1 234 567,89`.
+///
+/// Besides the ASCII space, the no-break space (U+00A0) and narrow no-break
+/// space (U+202F) are accepted between groups.
+pub struct SpaceComma;
+impl Separators for SpaceComma {
+    const THOUSANDS: &'static [char] = &[' ', '\u{A0}', '\u{202F}'];
+    const DECIMAL: char = ',';
+    const UINT_REGEX: &'static str = r"\+?(?:[0-9]{1,3}(?:[ \x{A0}\x{202F}][0-9]{3})+|[0-9]+)";
+    const INT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:[ \x{A0}\x{202F}][0-9]{3})+|[0-9]+)";
+    const FLOAT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:[ \x{A0}\x{202F}][0-9]{3})+|[0-9]+)(?:,[0-9]+)?";
+}
+
+/// Separators for numbers written like `1'234'567.89`.
+pub struct ApostropheDot;
+impl Separators for ApostropheDot {
+    const THOUSANDS: &'static [char] = &['\''];
+    const DECIMAL: char = '.';
+    const UINT_REGEX: &'static str = r"\+?(?:[0-9]{1,3}(?:'[0-9]{3})+|[0-9]+)";
+    const INT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:'[0-9]{3})+|[0-9]+)";
+    const FLOAT_REGEX: &'static str = r"[+-]?(?:[0-9]{1,3}(?:'[0-9]{3})+|[0-9]+)(?:\.[0-9]+)?";
+}
+
+/// Implementation of [`Scan`](crate::Scan) and [`DefaultScan`](crate::DefaultScan)
+/// for `T`, accepting digits grouped by thousands separators.
+///
+/// The separators are chosen by `S`, which defaults to [`CommaDot`]. Numbers
+/// without any separators are accepted as well.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanln_from, Grouped, DotComma, Decimal, Error};
+/// let mut input = "1,234,567".as_bytes();
+/// assert_eq!(1_234_567_u32, scanln_from!(&mut input, "{}", Grouped<u32>)?);
+///
+/// let mut input = "-1.234.567,89".as_bytes();
+/// let value = scanln_from!(&mut input, "{}", Grouped<Decimal, DotComma>)?;
+/// assert_eq!("-1234567.89", value.to_string());
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Grouped<T, S = CommaDot> { _phantom: PhantomData<(T, S)> }
+
+/// Removes the thousands separators from `s` and replaces its decimal
+/// separator with a `'.'`.
+fn ungroup<S: Separators>(s: &str) -> String {
+    s.chars()
+        .filter(|ch| !S::THOUSANDS.contains(ch))
+        .map(|ch| if ch == S::DECIMAL { '.' } else { ch })
+        .collect()
+}
+
+macro_rules! impl_scan_grouped {
+    ($regex:ident: $($output:ty),*) => {$(
+        impl<S: Separators> Scan for Grouped<$output, S> {
+            type Output = $output;
+            type Error = <$output as FromStr>::Err;
+            fn scan(s: &str) -> Result<Self::Output, Self::Error> {
+                <$output as FromStr>::from_str(&ungroup::<S>(s))
+            }
+        }
+        impl<S: Separators> DefaultScan for Grouped<$output, S> {
+            const DEFAULT_REGEX: &'static str = S::$regex;
+        }
+        // Numbers are printed without thousands separators, which are
+        // optional when scanning.
+        impl<S: Separators> Print for Grouped<$output, S> {
+            fn print(value: &$output, out: &mut dyn fmt::Write) -> fmt::Result {
+                value.to_string().chars()
+                    .map(|ch| if ch == '.' { S::DECIMAL } else { ch })
+                    .try_for_each(|ch| out.write_char(ch))
+            }
+        }
+    )*}
+}
+
+impl_scan_grouped!(UINT_REGEX: u8, u16, u32, u64, u128, usize);
+impl_scan_grouped!(INT_REGEX: i8, i16, i32, i64, i128, isize);
+impl_scan_grouped!(FLOAT_REGEX: f32, f64, Decimal);
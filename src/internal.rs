@@ -1,8 +1,15 @@
-use crate::error::{Result, ScanError::{self, *}, Utf8Error};
-use std::io::BufRead;
+use crate::error::{Error, Limit, Result, ScanError::{self, *}, Utf8Error};
+use crate::{Captures, Limits, Scanner, Scan, ScanFrom, DefaultScan};
+use once_cell::sync::Lazy;
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::error::Error as StdError;
+use std::io::{BufRead, Read};
+use std::ops::Deref;
+use std::sync::{Mutex, PoisonError};
 
 // Re-export certain items from regex so they're in a known location.
-pub use regex::{Regex, Error as RegexError};
+pub use regex::{Regex, RegexBuilder, Error as RegexError};
 
 /// A dummy function with the same signature as that returned by a call to
 /// `scanner`.
@@ -14,13 +21,307 @@ pub fn dummy<T>(_reader: &mut dyn BufRead) -> Result<T> {
     std::unimplemented!()
 }
 
+/// Flags applied to a regex when it is compiled.
+///
+/// Each flag is `None` unless explicitly set or cleared, in which case the
+/// regex library's default applies.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct RegexFlags {
+    pub case_insensitive: Option<bool>,
+    pub multi_line: Option<bool>,
+    pub dot_matches_new_line: Option<bool>,
+    pub swap_greed: Option<bool>,
+    pub ignore_whitespace: Option<bool>,
+    pub unicode: Option<bool>,
+}
+impl RegexFlags {
+    /// Returns a parser for the syntax of regexes compiled with these flags.
+    pub(crate) fn parser(self) -> regex_syntax::ParserBuilder {
+        let mut parser = regex_syntax::ParserBuilder::new();
+        parser
+            .case_insensitive(self.case_insensitive.unwrap_or(false))
+            .multi_line(self.multi_line.unwrap_or(false))
+            .dot_matches_new_line(self.dot_matches_new_line.unwrap_or(false))
+            .swap_greed(self.swap_greed.unwrap_or(false))
+            .ignore_whitespace(self.ignore_whitespace.unwrap_or(false))
+            .unicode(self.unicode.unwrap_or(true));
+        parser
+    }
+}
+
+/// A compiled regex, along with a copy anchored at the start of the input.
+///
+/// Captures only match at the start of the input, so they are matched with
+/// the anchored copy, which fails without searching the rest of the input.
+/// The regex itself is kept for its pattern.
+#[derive(Debug, Clone)]
+pub struct ScanRegex {
+    regex: Regex,
+    anchored: Regex,
+}
+impl Deref for ScanRegex {
+    type Target = Regex;
+    fn deref(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+/// Regexes that have been compiled by any scanner, keyed by their pattern and
+/// flags.
+static REGEX_CACHE: Lazy<Mutex<HashMap<(String, RegexFlags), ScanRegex>>> = Lazy::new(Default::default);
+
+/// Compiles a regex, or returns a copy of the same regex if it has already
+/// been compiled with the same flags.
+///
+/// Copies of a `Regex` share their compiled program, so scanners that use the
+/// same rules (such as the default regex of a common type) only compile it
+/// once per process.
+pub fn cached_regex(pattern: &str, flags: RegexFlags) -> Result<ScanRegex, RegexError> {
+    let key = (pattern.to_string(), flags);
+    if let Some(regex) = REGEX_CACHE.lock().unwrap_or_else(PoisonError::into_inner).get(&key) {
+        return Ok(regex.clone());
+    }
+
+    // Compile without holding the lock, since it can be slow. If another
+    // thread compiles the same regex meanwhile, the first one inserted wins.
+    let regex = compile_regex(pattern, flags)?;
+    let mut cache = REGEX_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(cache.entry(key).or_insert(regex).clone())
+}
+
+/// Compiles a regex without caching it.
+///
+/// This is used for patterns computed at runtime, which may differ every time
+/// a scanner is created and would otherwise fill the cache without bound.
+pub fn compile_regex(pattern: &str, flags: RegexFlags) -> Result<ScanRegex, RegexError> {
+    let mut builder = RegexBuilder::new(pattern);
+    if let Some(value) = flags.case_insensitive {
+        builder.case_insensitive(value);
+    }
+    if let Some(value) = flags.multi_line {
+        builder.multi_line(value);
+    }
+    if let Some(value) = flags.dot_matches_new_line {
+        builder.dot_matches_new_line(value);
+    }
+    if let Some(value) = flags.swap_greed {
+        builder.swap_greed(value);
+    }
+    if let Some(value) = flags.ignore_whitespace {
+        builder.ignore_whitespace(value);
+    }
+    if let Some(value) = flags.unicode {
+        builder.unicode(value);
+    }
+    let regex = builder.build()?;
+    let anchored = anchor_regex(pattern, flags).unwrap_or_else(|| regex.clone());
+    Ok(ScanRegex { regex, anchored })
+}
+
+/// Compiles a copy of a regex that only matches at the start of the input.
+///
+/// The pattern is anchored through its syntax tree, since text appended to
+/// the pattern itself could end up in a comment. The flags are part of the
+/// printed tree, so none are set on the copy.
+fn anchor_regex(pattern: &str, flags: RegexFlags) -> Option<Regex> {
+    use regex_syntax::hir::{Hir, Look};
+
+    let hir = flags.parser().build().parse(pattern).ok()?;
+    Regex::new(&Hir::concat(vec![Hir::look(Look::Start), hir]).to_string()).ok()
+}
+
+/// The macro used when a path given as a format string doesn't name a
+/// constant declared with `#[format_string]`.
+///
+/// The scanning macros import it with a glob, around an explicit import of
+/// the path under the same name. A `#[format_string]` constant comes with a
+/// macro of its own, which shadows this one.
+///
+/// ```compile_fail
+/// const FORMAT: &str = "{}";
+/// let scanner = rescan::scanner!(FORMAT, u32);
+/// ```
+pub mod format_fallback {
+    #[doc(hidden)]
+    #[macro_export]
+    macro_rules! __rescan_format_fallback {
+        ([$($mac:tt)*], [$($before:tt)*], [$($after:tt)*], [$($path:tt)*]) => {
+            $crate::_rescan_internal::format_fallback::not_format_string($($path)*)
+        };
+    }
+    pub use __rescan_format_fallback as __rescan_format;
+
+    /// Implemented by nothing, so that the error is reported at the path.
+    #[diagnostic::on_unimplemented(
+        message = "a constant used as a format string must be declared with `#[format_string]`",
+        label = "not declared with `#[format_string]`",
+    )]
+    pub trait FormatStringConst {}
+
+    pub fn not_format_string<T: FormatStringConst>(_path: T) -> ! {
+        unreachable!()
+    }
+}
+
+/// Storage for the scanner created by a single invocation of a scanning
+/// macro, such as `scanln!`.
+///
+/// Each invocation declares a `static CallSite`, so its scanner (and its
+/// compiled regexes) can be reused on every execution. A call site within a
+/// generic function may create scanners for different rule types, even with
+/// the same output type (as with `u32` and `Hex<u32>`), so one is kept for
+/// each tuple of rule types. Likewise, a regex that isn't a literal may have a
+/// different pattern each time, so one is kept for each value of the patterns.
+pub struct CallSite {
+    scanners: Mutex<Vec<CallSiteScanner>>,
+}
+
+/// A scanner kept by a [`CallSite`], with the rule types and patterns it was
+/// created for.
+struct CallSiteScanner {
+    rules: TypeId,
+    patterns: Box<[String]>,
+    scanner: &'static (dyn Any + Send + Sync),
+}
+impl CallSite {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            scanners: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the scanner of this call site for the rule types `R` and the
+    /// given patterns, creating it with `init` on the first call.
+    pub fn scanner<R: 'static, T: 'static, C: 'static>(&self, patterns: &[&str], init: impl FnOnce() -> Scanner<T, C>) -> &'static Scanner<T, C> {
+        let rules = TypeId::of::<R>();
+        let mut scanners = self.scanners.lock().unwrap_or_else(PoisonError::into_inner);
+        let cached = scanners.iter()
+            .filter(|cached| cached.rules == rules && cached.patterns.iter().eq(patterns))
+            .find_map(|cached| cached.scanner.downcast_ref());
+        if let Some(scanner) = cached {
+            return scanner;
+        }
+        // The scanner lives as long as its call site, which is `'static`.
+        let scanner = Box::leak(Box::new(init()));
+        scanners.push(CallSiteScanner {
+            rules,
+            patterns: patterns.iter().map(|&pattern| pattern.to_owned()).collect(),
+            scanner,
+        });
+        scanner
+    }
+}
+
+/// Storage for the regexes compiled by a single capture of `scan_into!`.
+///
+/// Each capture declares a `static CallSiteRegex`, so its regex is compiled
+/// once and shared by every execution. The default regex of a capture
+/// depends on the type of its place, which may differ between the
+/// instantiations of a generic function, so a regex is kept for each pattern.
+pub struct CallSiteRegex {
+    regexes: Mutex<Vec<(&'static str, ScanRegex)>>,
+}
+impl CallSiteRegex {
+    #[allow(clippy::new_without_default)]
+    pub const fn new() -> Self {
+        Self {
+            regexes: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the regex of this call site for `pattern`, compiling it on the
+    /// first call.
+    pub fn regex(&self, pattern: &'static str, flags: RegexFlags) -> Result<ScanRegex, Error> {
+        let mut regexes = self.regexes.lock().unwrap_or_else(PoisonError::into_inner);
+        if let Some((_, regex)) = regexes.iter().find(|(cached, _)| *cached == pattern) {
+            return Ok(regex.clone());
+        }
+        let regex = compile_regex(pattern, flags).map_err(|error| Error::from(&error))?;
+        regexes.push((pattern, regex.clone()));
+        Ok(regex)
+    }
+}
+
+/// Scans a nested capture (`use EXPR`) in a scanner with a context.
+///
+/// A nested `Scanner` with the same context type is scanned with the context,
+/// and any other `ScanFrom` value is scanned without it. The `How` parameter
+/// keeps the two implementations apart; it is inferred from the type of the
+/// nested value.
+pub trait NestedScan<Ctx: ?Sized, How> {
+    type Output;
+    fn scan_nested(&self, reader: &mut dyn BufRead, ctx: &mut Ctx) -> Result<Self::Output>;
+}
+
+/// Marks the [`NestedScan`] implementation for scanners with a context.
+pub struct WithContext;
+/// Marks the [`NestedScan`] implementation for `ScanFrom` values.
+pub struct WithoutContext;
+
+impl<T, C> NestedScan<C, WithContext> for Scanner<T, C> {
+    type Output = T;
+    fn scan_nested(&self, reader: &mut dyn BufRead, ctx: &mut C) -> Result<T> {
+        self.scan_with(reader, ctx)
+    }
+}
+impl<S: ScanFrom + ?Sized, C: ?Sized> NestedScan<C, WithoutContext> for S {
+    type Output = S::Output;
+    fn scan_nested(&self, reader: &mut dyn BufRead, _ctx: &mut C) -> Result<S::Output> {
+        self.scan_from(reader)
+    }
+}
+
+/// An input accepted by the `scan!` and `try_scan!` macros: a string or byte
+/// slice, which is scanned from its start, or a mutable reference to a
+/// `BufRead`, which is advanced past the scanned text.
+pub trait ScanSource {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T>;
+}
+impl ScanSource for &str {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_str(self)
+    }
+}
+impl ScanSource for &String {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_str(self)
+    }
+}
+impl ScanSource for String {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_str(&self)
+    }
+}
+impl ScanSource for &[u8] {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_slice(self)
+    }
+}
+impl<const N: usize> ScanSource for &[u8; N] {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_slice(self)
+    }
+}
+impl ScanSource for &Vec<u8> {
+    fn scan_source<T>(self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan_slice(self)
+    }
+}
+impl<R: BufRead + ?Sized> ScanSource for &mut R {
+    fn scan_source<T>(mut self, scanner: &Scanner<T>) -> Result<T> {
+        scanner.scan(&mut self)
+    }
+}
+
 /// Attempts to read the string `lit` from the reader. If successful, the
 /// reader is automatically advanced past the match. Otherwise, an error
 /// results, and the reader will have advanced past some prefix of `lit`.
-pub fn match_literal(reader: &mut dyn BufRead, mut lit: &str) -> Result<(), ScanError> {
+pub fn match_literal(reader: &mut dyn ScanInput, mut lit: &str) -> Result<(), ScanError> {
+    reader.check_match(lit.len(), false)?;
     let mismatch_error = Err(ScanLiteralError(lit.into()));
     while !lit.is_empty() {
-        let buf = try_read_str(reader)?;
+        let buf = reader.fill_str()?;
 
         if lit.len() <= buf.len() {
             if buf.starts_with(lit) {
@@ -38,14 +339,34 @@ pub fn match_literal(reader: &mut dyn BufRead, mut lit: &str) -> Result<(), Scan
     Ok(())
 }
 
+/// Attempts to read the string `lit` from the reader, ignoring differences in
+/// case. If successful, the reader is automatically advanced past the match.
+/// Otherwise, an error results, and the reader will have advanced past some
+/// prefix of the input.
+///
+/// Characters are compared using their lowercase mappings, one at a time.
+pub fn match_literal_ignore_case(reader: &mut dyn ScanInput, lit: &str) -> Result<(), ScanError> {
+    reader.check_match(lit.len(), false)?;
+    let mismatch_error = || ScanLiteralError(lit.into());
+    for expected in lit.chars() {
+        let buf = reader.fill_str()?;
+        let actual = buf.chars().next().ok_or_else(mismatch_error)?;
+        if actual != expected && !actual.to_lowercase().eq(expected.to_lowercase()) {
+            return Err(mismatch_error());
+        }
+        reader.consume(actual.len_utf8());
+    }
+    Ok(())
+}
+
 /// Attempts to match the given regex at the start of the reader. If
 /// successful, the matched portion of the string is returned. Otherwise, an
 /// error is returned. In any case, the reader is not advanced---this must
 /// be done manually by calling the `advance_from_regex` function with the
 /// length of the match from this function.
-pub fn match_regex<'r>(reader: &'r mut dyn BufRead, re: &Regex) -> Result<&'r str, ScanError> {
-    let buf = try_read_str(reader)?;
-    if let Some(range) = re.find(buf) {
+pub fn match_regex<'r>(reader: &'r mut dyn ScanInput, re: &ScanRegex) -> Result<&'r str, ScanError> {
+    let buf = reader.fill_str()?;
+    if let Some(range) = re.anchored.find(buf) {
         if range.start() == 0 {
             return Ok(range.as_str());
         }
@@ -55,13 +376,223 @@ pub fn match_regex<'r>(reader: &'r mut dyn BufRead, re: &Regex) -> Result<&'r st
 
 /// Advance the reader by the given string. This should only be called with
 /// the length of the match previously returned from `match_regex`.
-pub fn advance_from_regex(reader: &mut dyn BufRead, match_len: usize) {
+pub fn advance_from_regex(reader: &mut dyn ScanInput, match_len: usize) {
     reader.consume(match_len);
 }
 
+/// Matches `re` at the start of the reader and parses the match with `scan`.
+/// If both succeed, the reader is advanced past the match and the parsed
+/// value is returned.
+pub fn scan_capture<V, E: StdError + 'static>(reader: &mut dyn ScanInput, re: &ScanRegex, scan: impl FnOnce(&str) -> Result<V, E>) -> Result<V, Error> {
+    let str_len = match_regex(reader, re)?.len();
+    reader.check_match(str_len, true)?;
+    // The buffer is unchanged until it is consumed, so it still starts with
+    // the match.
+    let str = &reader.fill_str()?[..str_len];
+    let val = scan(str).map_err(Error::from_parse_error)?;
+    advance_from_regex(reader, str_len);
+    Ok(val)
+}
+
+/// Matches `re` at the start of the reader and advances past the match,
+/// discarding it.
+pub fn skip_regex(reader: &mut dyn ScanInput, re: &ScanRegex) -> Result<(), ScanError> {
+    let str_len = match_regex(reader, re)?.len();
+    reader.check_match(str_len, true)?;
+    advance_from_regex(reader, str_len);
+    Ok(())
+}
+
+/// Scans a value of the type of `place` with its default regex, storing it in
+/// `place`. Used by `scan_into!`.
+pub fn scan_into_place<T: DefaultScan<Output = T>>(reader: &mut dyn ScanInput, place: &mut T, site: &CallSiteRegex) -> Result<(), Error> where T::Error: 'static {
+    scan_into_place_with(reader, place, site, T::DEFAULT_REGEX, RegexFlags::default())
+}
+
+/// Scans a value of the type of `place` matching the regex `pattern`, storing
+/// it in `place`. Used by `scan_into!`.
+pub fn scan_into_place_with<T: Scan<Output = T>>(reader: &mut dyn ScanInput, place: &mut T, site: &CallSiteRegex, pattern: &'static str, flags: RegexFlags) -> Result<(), Error> where T::Error: 'static {
+    let re = site.regex(pattern, flags)?;
+    *place = scan_capture(reader, &re, T::scan)?;
+    Ok(())
+}
+
+/// Matches the regex `pattern` and advances past the match. Used by
+/// `scan_into!`.
+pub fn skip_pattern(reader: &mut dyn ScanInput, site: &CallSiteRegex, pattern: &'static str, flags: RegexFlags) -> Result<(), Error> {
+    let re = site.regex(pattern, flags)?;
+    Ok(skip_regex(reader, &re)?)
+}
+
+/// Returns the number of places assigned by `scan_into!`, or its error.
+///
+/// As with `scanf`, input that doesn't match the format ends the scan without
+/// an error.
+pub fn scan_into_count(count: usize, result: Result<(), Error>) -> Result<usize> {
+    match result {
+        Ok(()) | Err(Error::ParseError(_) | Error::ScanError(ScanLiteralError(_) | ScanRegexError(_))) => Ok(count),
+        Err(error) => Err(error),
+    }
+}
+
+/// Receives the captures of a failed scan, when it was started by
+/// [`Scanner::scan_partial`](crate::Scanner::scan_partial).
+pub trait PartialSink<T> {
+    /// Keeps the captures of a scan that failed at `segment`.
+    fn keep(&mut self, segment: usize, captures: T::Partial) where T: Captures;
+
+    /// Keeps the only capture of a scan that failed at `segment`.
+    fn keep_whole(&mut self, segment: usize, capture: Option<T>);
+}
+
+/// The input to a scanning function: a [`BufRead`] whose buffer can be read as
+/// a string.
+pub trait ScanInput: BufRead {
+    /// Returns the longest valid UTF-8 prefix of the buffer, filling it if it
+    /// is empty, or a `ScanError` if there are invalid bytes at its start.
+    fn fill_str(&mut self) -> Result<&str, ScanError>;
+
+    /// Checks that a match of `len` bytes may be consumed. `is_capture` is
+    /// `true` if the match is the text of a capture, rather than a literal.
+    fn check_match(&mut self, _len: usize, _is_capture: bool) -> Result<(), ScanError> {
+        Ok(())
+    }
+}
+
+/// Adapts any [`BufRead`] into a [`ScanInput`], validating its buffer each
+/// time it is read.
+pub struct ReaderInput<'a>(pub &'a mut dyn BufRead);
+impl Read for ReaderInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+impl BufRead for ReaderInput<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.0.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.0.consume(amt)
+    }
+}
+impl ScanInput for ReaderInput<'_> {
+    fn fill_str(&mut self) -> Result<&str, ScanError> {
+        try_read_str(self.0)
+    }
+}
+
+/// A [`ScanInput`] that enforces the capture and scan length [`Limits`] of a
+/// scanner.
+pub struct LimitedInput<'a> {
+    input: &'a mut dyn ScanInput,
+    limits: Limits,
+    consumed: usize,
+}
+impl<'a> LimitedInput<'a> {
+    pub fn new(input: &'a mut dyn ScanInput, limits: Limits) -> Self {
+        Self { input, limits, consumed: 0 }
+    }
+}
+impl Read for LimitedInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.input.read(buf)?;
+        self.consumed += len;
+        Ok(len)
+    }
+}
+impl BufRead for LimitedInput<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        self.input.fill_buf()
+    }
+    fn consume(&mut self, amt: usize) {
+        self.consumed += amt;
+        self.input.consume(amt)
+    }
+}
+impl ScanInput for LimitedInput<'_> {
+    fn fill_str(&mut self) -> Result<&str, ScanError> {
+        self.input.fill_str()
+    }
+    fn check_match(&mut self, len: usize, is_capture: bool) -> Result<(), ScanError> {
+        if let (Some(max), true) = (self.limits.max_capture_len, is_capture) {
+            if len > max {
+                return Err(ScanLimitError(Limit::CaptureLength(max)));
+            }
+        }
+        if let Some(max) = self.limits.max_scan_len {
+            if self.consumed + len > max {
+                return Err(ScanLimitError(Limit::ScanLength(max)));
+            }
+        }
+        self.input.check_match(len, is_capture)
+    }
+}
+
+/// A [`ScanInput`] over a byte slice, which is validated as UTF-8 only once.
+pub struct SliceInput<'a> {
+    bytes: &'a [u8],
+    valid_up_to: usize,
+}
+impl<'a> SliceInput<'a> {
+    pub fn new(bytes: &'a [u8]) -> Self {
+        Self { bytes, valid_up_to: valid_up_to(bytes) }
+    }
+
+    /// Creates an input over a string, which needs no validation.
+    pub fn from_text(text: &'a str) -> Self {
+        Self { bytes: text.as_bytes(), valid_up_to: text.len() }
+    }
+
+    /// Returns the number of bytes that have not been consumed.
+    pub fn remaining(&self) -> usize {
+        self.bytes.len()
+    }
+}
+
+/// Returns the length of the longest valid UTF-8 prefix of `bytes`.
+fn valid_up_to(bytes: &[u8]) -> usize {
+    std::str::from_utf8(bytes).map_or_else(|error| error.valid_up_to(), str::len)
+}
+impl Read for SliceInput<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let len = self.bytes.len().min(buf.len());
+        buf[..len].copy_from_slice(&self.bytes[..len]);
+        self.consume(len);
+        Ok(len)
+    }
+}
+impl BufRead for SliceInput<'_> {
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        Ok(self.bytes)
+    }
+    fn consume(&mut self, amt: usize) {
+        self.bytes = &self.bytes[amt..];
+        // Scanning functions only consume whole chars, but other readers of
+        // this input (such as a nested `ScanFrom`) might not.
+        let at_char_boundary = self.bytes.first().is_none_or(|b| !(0x80..0xc0).contains(b));
+        if amt <= self.valid_up_to && at_char_boundary {
+            self.valid_up_to -= amt;
+        } else {
+            self.valid_up_to = valid_up_to(self.bytes);
+        }
+    }
+}
+impl ScanInput for SliceInput<'_> {
+    fn fill_str(&mut self) -> Result<&str, ScanError> {
+        if self.valid_up_to == 0 && !self.bytes.is_empty() {
+            return longest_utf8_prefix(self.bytes).map_err(|error_bytes| {
+                Utf8Error::new(error_bytes).into()
+            });
+        }
+        // SAFETY: The bytes up to `valid_up_to` were validated as UTF-8, and
+        // `consume` revalidates them unless it stopped at a char boundary.
+        Ok(unsafe { std::str::from_utf8_unchecked(&self.bytes[..self.valid_up_to]) })
+    }
+}
+
 /// Returns the longest valid UTF-8 sequence from the reader, or a
 /// `ScanError` if there are invalid bytes at the start.
-fn try_read_str(reader: &mut dyn BufRead) -> Result<&str, ScanError> {
+pub(crate) fn try_read_str(reader: &mut dyn BufRead) -> Result<&str, ScanError> {
     let buf = reader.fill_buf()?;
     longest_utf8_prefix(buf).map_err(|error_bytes| {
         Utf8Error::new(error_bytes).into()
@@ -91,6 +622,50 @@ fn longest_utf8_prefix(bytes: &[u8]) -> Result<&str, &[u8]> {
     })
 }
 
+#[test]
+fn cached_regex_test() {
+    let flags = RegexFlags { case_insensitive: Some(true), ..RegexFlags::default() };
+    let regex = cached_regex("ab+c", flags).unwrap();
+    assert!(regex.is_match("ABBC"));
+    assert!(!cached_regex("ab+c", RegexFlags::default()).unwrap().is_match("ABBC"));
+    assert!(cached_regex("ab(", flags).is_err());
+
+    // Uncached regexes are compiled the same way, but not kept.
+    let pattern = "runtime [0-9]+ pattern";
+    assert!(compile_regex(pattern, flags).unwrap().is_match("RUNTIME 42 PATTERN"));
+    let cache = REGEX_CACHE.lock().unwrap_or_else(PoisonError::into_inner);
+    assert!(!cache.keys().any(|(cached, _)| cached == pattern));
+}
+
+#[test]
+fn match_regex_test() {
+    fn matched(pattern: &str, flags: RegexFlags, text: &str) -> Option<String> {
+        let re = compile_regex(pattern, flags).unwrap();
+        match_regex(&mut SliceInput::from_text(text), &re).ok().map(str::to_string)
+    }
+
+    assert_eq!(Some("abb".into()), matched("ab+", RegexFlags::default(), "abbc"));
+    assert_eq!(None, matched("b+", RegexFlags::default(), "abbc"));
+    assert_eq!(Some("".into()), matched("b*", RegexFlags::default(), "abbc"));
+    // A match after a line break isn't at the start, even in multi-line mode.
+    let flags = RegexFlags { multi_line: Some(true), ..RegexFlags::default() };
+    assert_eq!(None, matched("^b", flags, "a\nb"));
+    // Flags and trailing comments carry over to the anchored copy.
+    let flags = RegexFlags { case_insensitive: Some(true), ignore_whitespace: Some(true), ..RegexFlags::default() };
+    assert_eq!(Some("AB".into()), matched("a b # comment", flags, "ABC"));
+    assert_eq!(Some("xyz".into()), matched("x(?x) y z # comment", RegexFlags::default(), "xyz"));
+}
+
+#[test]
+fn match_literal_ignore_case_test() {
+    let mut reader = "ERROR: Straße".as_bytes();
+    assert!(match_literal_ignore_case(&mut ReaderInput(&mut reader), "error: STRAßE").is_ok());
+    assert!(reader.is_empty());
+
+    let mut reader = "Warn".as_bytes();
+    assert!(match_literal_ignore_case(&mut ReaderInput(&mut reader), "warning").is_err());
+}
+
 #[test]
 fn longest_utf8_prefix_test() {
     let full = "ăѣ𝔠";
@@ -103,3 +678,18 @@ fn longest_utf8_prefix_test() {
     assert_eq!(Ok("ăѣ"), longest_utf8_prefix(&full.as_bytes()[..7]));
     assert_eq!(Ok("ăѣ𝔠"), longest_utf8_prefix(&full.as_bytes()[..8]));
 }
+
+#[test]
+fn slice_input_test() {
+    let mut input = SliceInput::new(b"ab\xc3\xa9c\xffd");
+    assert_eq!("ab\u{e9}c", input.fill_str().unwrap());
+    input.consume(3);
+    // Consuming part of a char revalidates the rest.
+    assert!(matches!(input.fill_str(), Err(ScanDecodeError(_))));
+    input.consume(1);
+    assert_eq!("c", input.fill_str().unwrap());
+    input.consume(1);
+    assert!(matches!(input.fill_str(), Err(ScanDecodeError(_))));
+    input.consume(1);
+    assert_eq!("d", input.fill_str().unwrap());
+}
@@ -1,19 +1,32 @@
 mod internal;
+mod decimal;
+mod quoted;
 pub mod error;
 mod example;
 mod impls;
 mod scanner;
+mod parallel;
+mod find;
+mod sample;
 pub mod readers;
 #[doc(hidden)]
 pub mod _rescan_internal {
     pub use crate::internal::*;
 }
 
-pub use rescan_macros::scanner;
-pub use scanner::Scanner;
-pub use error::{Error, Result};
+pub use rescan_macros::{scanner, scan_into, format_string};
+pub use scanner::{Scanner, Limits};
+pub use parallel::ParallelOptions;
+pub use find::{Match, FindIter};
+pub use sample::{Sampler, Sample};
+pub use error::{Error, Result, PartialScan};
 pub use impls::{Binary, Octal, Hex};
+pub use impls::{Grouped, Separators, CommaDot, DotComma, SpaceComma, ApostropheDot};
+pub use decimal::{Decimal, ParseDecimalError};
+pub use quoted::{Quoted, QuoteStyle, DoubleQuotes, SingleQuotes, ShellQuotes, QuotedError, UnquoteError};
 use std::error::Error as StdError;
+use std::fmt;
+use std::io::BufRead;
 
 /// Parse a value from a string.
 ///
@@ -23,9 +36,10 @@ use std::error::Error as StdError;
 /// regular expression.
 ///
 /// The `Output` associated type is the type produced by `scan` when successful.
-/// This will usually be `Self`, but can be any type. This is useful to enable
-/// scanning of foreign types (circumventing the orphan rule), and to provide
-/// alternative parsing rules for a given type. (See, for example, [`Hex`].)
+/// This will usually be `Self`, but can be any type that owns its data. This
+/// is useful to enable scanning of foreign types (circumventing the orphan
+/// rule), and to provide alternative parsing rules for a given type. (See, for
+/// example, [`Hex`].)
 ///
 /// The `Error` associated type is the returned failure value of `scan`. It can
 /// be any type implementing the [`std::error::Error`] trait.
@@ -40,6 +54,62 @@ pub trait Scan {
     fn scan(text: &str) -> Result<Self::Output, Self::Error>;
 }
 
+/// Parse a value from a string, with access to a caller-provided context.
+///
+/// `ScanWith` generalizes [`Scan`] for parsing that depends on runtime state,
+/// such as an intern table or the current year. The type of the context is
+/// declared with a `#[context(Type)]` attribute on the format string, and the
+/// context itself is given to [`Scanner::scan_with`]. It is passed to every
+/// capture in turn, which may both read and update it.
+///
+/// Every `Scan` type implements `ScanWith<C>` for any context `C` by ignoring
+/// it, so context-aware and ordinary types can be mixed within one scanner.
+/// Since context-aware types don't implement [`DefaultScan`], they must
+/// always be given with an explicit regex.
+///
+/// Likewise, a nested scanner (`use EXPR`) that takes the same context is
+/// scanned with it, and any other [`ScanFrom`] value is scanned without it.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanner, ScanWith, Error};
+/// # use std::convert::Infallible;
+/// /// An index into a table of interned names.
+/// struct Symbol;
+/// impl ScanWith<Vec<String>> for Symbol {
+///     type Output = usize;
+///     type Error = Infallible;
+///     fn scan_with(text: &str, names: &mut Vec<String>) -> Result<usize, Infallible> {
+///         Ok(names.iter().position(|name| name == text).unwrap_or_else(|| {
+///             names.push(text.into());
+///             names.len() - 1
+///         }))
+///     }
+/// }
+///
+/// let mut names = vec![];
+/// let scanner = scanner!(#[context(Vec<String>)] "{} -> {}", r"\w+" as Symbol, r"\w+" as Symbol);
+/// assert_eq!((0, 1), scanner.scan_with(&mut "a -> b".as_bytes(), &mut names)?);
+/// assert_eq!((1, 0), scanner.scan_with(&mut "b -> a".as_bytes(), &mut names)?);
+/// assert_eq!(vec!["a", "b"], names);
+/// # Ok::<(), Error>(())
+/// ```
+pub trait ScanWith<Ctx: ?Sized> {
+    type Output: Sized;
+    type Error: StdError;
+
+    /// Parse a string to return a value of type `Self::Output`, using the
+    /// context `ctx`.
+    fn scan_with(text: &str, ctx: &mut Ctx) -> Result<Self::Output, Self::Error>;
+}
+impl<T: Scan, Ctx: ?Sized> ScanWith<Ctx> for T {
+    type Output = T::Output;
+    type Error = T::Error;
+    fn scan_with(text: &str, _ctx: &mut Ctx) -> Result<Self::Output, Self::Error> {
+        T::scan(text)
+    }
+}
+
 /// Parse a value from a string with a default regular expression.
 ///
 /// This trait extends [`Scan`] with a default regular expression. This allows
@@ -66,3 +136,117 @@ pub trait DefaultScan: Scan {
     /// The default regex to use in a scanning macro when none is specified.
     const DEFAULT_REGEX: &'static str;
 }
+
+/// Format a value so that it can be scanned again.
+///
+/// `Print` is the counterpart of [`Scan`]: it writes a value of type
+/// `Self::Output` as text that `scan` parses back into an equal value. It is
+/// used by printable scanners (see [`Scanner::format`]) to print each capture.
+/// For most types, this is the same as their `Display` implementation, while
+/// adaptors such as [`Hex`] and [`Quoted`] print in their own notation.
+///
+/// A printed value is only guaranteed to scan again if it is also accepted by
+/// the regex of its capture.
+///
+/// # Example
+/// ```
+/// # use rescan::{Print, Hex};
+/// let mut text = String::new();
+/// <Hex<i32> as Print>::print(&-255, &mut text)?;
+/// assert_eq!("-ff", text);
+/// # Ok::<(), std::fmt::Error>(())
+/// ```
+pub trait Print: Scan {
+    /// Write `value` to `out` in a form accepted by `scan`.
+    fn print(value: &Self::Output, out: &mut dyn fmt::Write) -> fmt::Result;
+}
+
+/// Read a value directly from a reader.
+///
+/// Whereas [`Scan`] parses a string that has already been matched by a regex,
+/// `ScanFrom` consumes its input from the reader itself. This is how scanners
+/// are nested: any `ScanFrom` value can be given to a scanning macro as an
+/// argument of the form `use EXPR`, and will be invoked at the position of
+/// its capture.
+///
+/// `ScanFrom` is implemented for [`Scanner`], and can be implemented for other
+/// types (such as a unit struct standing for a particular format) by
+/// delegating to a scanner.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanner, Scanner, Error};
+/// # use once_cell::sync::Lazy;
+/// static POINT: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!("({},{})", i32, i32));
+///
+/// let scanner = scanner!("{} -> {}", use POINT, use POINT);
+/// let (from, to) = scanner.scan(&mut "(1,2) -> (3,-4)".as_bytes())?;
+/// assert_eq!((1, 2), from);
+/// assert_eq!((3, -4), to);
+/// # Ok::<(), Error>(())
+/// ```
+pub trait ScanFrom {
+    type Output;
+
+    /// Read a value of type `Self::Output` from the start of `reader`.
+    fn scan_from(&self, reader: &mut dyn BufRead) -> Result<Self::Output>;
+
+    /// Write a random input for `scan_from` to `out`, returning the value it
+    /// is expected to scan to, if known.
+    ///
+    /// This is used to generate samples of the scanners this value is nested
+    /// in (see [`Scanner::sample`]). By default, nothing is written and `None`
+    /// is returned.
+    fn sample_into(&self, sampler: &mut Sampler, out: &mut String) -> Option<Self::Output> {
+        let _ = (sampler, out);
+        None
+    }
+}
+
+/// The captures of a scanner's output, as kept by a failed scan.
+///
+/// When [`Scanner::scan_partial`] fails, the captures scanned before the
+/// failure are returned as a value of type `Self::Partial`. For a scanner with
+/// several captures, whose output is a tuple, this is a tuple of the same
+/// length with each element wrapped in an `Option`. For a scanner with a
+/// single capture, it is an `Option` of that capture. (A single capture whose
+/// type is itself a tuple is split as if each element were a capture.)
+///
+/// `Captures` is implemented for tuples of up to 12 elements and for the
+/// outputs of this crate's [`Scan`] implementations. To keep a single capture
+/// of another type, implement it with `Partial = Option<Self>`.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanner, Captures, Scan, DefaultScan};
+/// # use std::convert::Infallible;
+/// #[derive(Debug)]
+/// struct Word(String);
+/// impl Scan for Word {
+///     type Output = Self;
+///     type Error = Infallible;
+///     fn scan(text: &str) -> Result<Self, Infallible> {
+///         Ok(Word(text.into()))
+///     }
+/// }
+/// impl DefaultScan for Word {
+///     const DEFAULT_REGEX: &'static str = r"\w+";
+/// }
+/// impl Captures for Word {
+///     type Partial = Option<Self>;
+///     fn from_whole(value: Option<Self>) -> Option<Self> {
+///         value
+///     }
+/// }
+///
+/// let scanner = scanner!("{}!", Word);
+/// let partial = scanner.scan_partial(&mut "hello?".as_bytes()).unwrap_err();
+/// assert_eq!(Some("hello"), partial.captures().as_ref().map(|word| word.0.as_str()));
+/// ```
+pub trait Captures: Sized {
+    type Partial;
+
+    /// Returns the partial form of a value scanned as a single capture, or
+    /// the partial form with no captures if `value` is `None`.
+    fn from_whole(value: Option<Self>) -> Self::Partial;
+}
@@ -1,9 +1,166 @@
-// Input might look like this: {string: "hello", int: 42, char: 'ß', bool: false, float: 3.14, ignore: _}
-fn main() {
-    println!("{}", rescan::scanln!("{}", rescan::Hex<i32>).unwrap());
+//! The `rescan` command-line tool, which converts lines of text to structured
+//! records.
 
-    //let stdin = std::io::stdin();
-    //let scanner = rescan::scanner!("{}", ".*" as String);
+mod cli;
 
-    //scanner.scan_lines(&mut stdin.lock()).map(Result::unwrap).for_each(|(str,)| println!("{}", str));
+use cli::args::{self, Command, FilterOptions, InvalidPolicy, Options};
+use cli::filter::Predicate;
+use cli::pattern::{Pattern, Value};
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::ops::ControlFlow;
+use std::process::ExitCode;
+
+/// Every line matched.
+const EXIT_SUCCESS: u8 = 0;
+/// At least one line didn't match the format, or couldn't be compared as
+/// `--where` requires.
+const EXIT_MISMATCH: u8 = 1;
+/// Invalid arguments or an I/O error.
+const EXIT_ERROR: u8 = 2;
+
+fn main() -> ExitCode {
+    let options = match args::parse_args(std::env::args().skip(1)) {
+        Ok(Command::Scan(options)) => options,
+        Ok(Command::Help) => {
+            print!("{}", args::USAGE);
+            return ExitCode::from(EXIT_SUCCESS);
+        }
+        Err(err) => {
+            eprintln!("rescan: {}\n\n{}", err, args::USAGE);
+            return ExitCode::from(EXIT_ERROR);
+        }
+    };
+    match run(&options) {
+        Ok(status) => ExitCode::from(status),
+        Err(err) => {
+            eprintln!("rescan: {}", err);
+            ExitCode::from(EXIT_ERROR)
+        }
+    }
+}
+
+/// Scans every input according to `options`, returning the exit status.
+fn run(options: &Options) -> Result<u8, String> {
+    let pattern = Pattern::new(&options.format, &options.types, &options.regexes)?;
+    let predicates = options.predicates.iter()
+        .map(|expr| {
+            let predicate = Predicate::parse(expr)?;
+            let index = pattern.index_of(predicate.name())
+                .ok_or_else(|| format!("there is no capture named `{}`", predicate.name()))?;
+            Ok((index, predicate))
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let stdout = io::stdout();
+    let mut out = BufWriter::new(stdout.lock());
+    let status = match &options.filter {
+        Some(filter) => run_filter(options, filter, &pattern, &predicates, &mut out)?,
+        None => run_convert(options, &pattern, &predicates, &mut out)?,
+    };
+    out.flush().map_err(|err| err.to_string())?;
+    Ok(status)
+}
+
+/// Prints a record for each line of input.
+///
+/// Lines that don't match the pattern, or whose captures can't be compared as
+/// `--where` requires, are reported as invalid.
+fn run_convert(options: &Options, pattern: &Pattern, predicates: &[(usize, Predicate)], out: &mut dyn Write) -> Result<u8, String> {
+    options.output.write_header(out, pattern.names()).map_err(|err| err.to_string())?;
+    let mut status = EXIT_SUCCESS;
+    for_each_line(&options.files, |file, line_number, line| {
+        let record = pattern.scan(line)
+            .map_err(|err| err.to_string())
+            .and_then(|values| Ok(is_selected(predicates, &values)?.then_some(values)));
+        match record {
+            Ok(Some(values)) => {
+                options.output.write_record(out, pattern.names(), &values).map_err(|err| err.to_string())?;
+            }
+            Ok(None) => {}
+            Err(err) => {
+                eprintln!("rescan: {}:{}: {}", file, line_number, err);
+                status = EXIT_MISMATCH;
+                if options.on_invalid == InvalidPolicy::Strict {
+                    return Ok(ControlFlow::Break(()));
+                }
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    Ok(status)
+}
+
+/// Prints the lines of input that match the pattern and predicates.
+///
+/// Records whose captures can't be compared as `--where` requires are
+/// reported, and neither selected nor rejected.
+fn run_filter(options: &Options, filter: &FilterOptions, pattern: &Pattern, predicates: &[(usize, Predicate)], out: &mut dyn Write) -> Result<u8, String> {
+    let only = match &filter.only {
+        Some(name) => Some(pattern.index_of(name).ok_or_else(|| format!("there is no capture named `{}`", name))?),
+        None => None,
+    };
+    let mut count = 0_usize;
+    for_each_line(&options.files, |file, line_number, line| {
+        let values = pattern.scan(line).ok();
+        let selected = match &values {
+            Some(values) => match is_selected(predicates, values) {
+                Ok(selected) => selected,
+                Err(err) => {
+                    eprintln!("rescan: {}:{}: {}", file, line_number, err);
+                    return Ok(ControlFlow::Continue(()));
+                }
+            },
+            None => false,
+        };
+        if selected != filter.invert {
+            count += 1;
+            if !filter.count {
+                let result = match (only, &values) {
+                    (Some(idx), Some(values)) => writeln!(out, "{}", values[idx]),
+                    _ => out.write_all(line).and_then(|()| out.write_all(b"\n")),
+                };
+                result.map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(ControlFlow::Continue(()))
+    })?;
+    if filter.count {
+        writeln!(out, "{}", count).map_err(|err| err.to_string())?;
+    }
+    Ok(if count > 0 { EXIT_SUCCESS } else { EXIT_MISMATCH })
+}
+
+/// Tests whether a record satisfies every predicate.
+fn is_selected(predicates: &[(usize, Predicate)], values: &[Value]) -> Result<bool, String> {
+    for (idx, predicate) in predicates {
+        if !predicate.test(&values[*idx])? {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Calls `f` with the name, line number and contents of each line of the
+/// given files, or of standard input if there are none, until it breaks.
+fn for_each_line(files: &[String], mut f: impl FnMut(&str, usize, &[u8]) -> Result<ControlFlow<()>, String>) -> Result<(), String> {
+    let stdin = ["-".to_string()];
+    let files = if files.is_empty() { &stdin[..] } else { files };
+    for file in files {
+        let mut reader: Box<dyn BufRead> = if file == "-" {
+            Box::new(io::stdin().lock())
+        } else {
+            let file = File::open(file).map_err(|err| format!("{}: {}", file, err))?;
+            Box::new(BufReader::new(file))
+        };
+
+        let mut line_number = 0;
+        while let Some(line) = rescan::readers::read_line(&mut reader).map_err(|err| format!("{}: {}", file, err))? {
+            line_number += 1;
+            if f(file, line_number, &line)?.is_break() {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
 }
@@ -0,0 +1,306 @@
+use crate::{Scanner, Result};
+use crate::error::{Limit, ScanError};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Result as IoResult};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::Path;
+use std::sync::{mpsc, Mutex, PoisonError};
+
+/// Options for the parallel scanning functions of [`Scanner`], such as
+/// [`par_scan_slice`](Scanner::par_scan_slice).
+///
+/// The input is split into chunks of whole lines, which are scanned by a pool
+/// of threads. By default, one thread is used per available CPU, chunks are
+/// about 1 MiB, and results are delivered in their original order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ParallelOptions {
+    threads: usize,
+    chunk_size: usize,
+    ordered: bool,
+}
+impl ParallelOptions {
+    /// Creates the default options.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the number of scanning threads. At least one thread is used.
+    pub fn threads(mut self, threads: usize) -> Self {
+        self.threads = threads.max(1);
+        self
+    }
+
+    /// Sets the approximate size of each chunk in bytes. Chunks are extended
+    /// to the end of their last line, so they may be larger.
+    pub fn chunk_size(mut self, chunk_size: usize) -> Self {
+        self.chunk_size = chunk_size.max(1);
+        self
+    }
+
+    /// Delivers results as soon as their chunk has been scanned, rather than
+    /// in their original order.
+    ///
+    /// In order, the results of a chunk that finishes early are held until
+    /// every chunk before it has been delivered.
+    pub fn unordered(mut self) -> Self {
+        self.ordered = false;
+        self
+    }
+}
+impl Default for ParallelOptions {
+    fn default() -> Self {
+        Self {
+            threads: std::thread::available_parallelism().map_or(1, usize::from),
+            chunk_size: 1 << 20,
+            ordered: true,
+        }
+    }
+}
+
+impl<T: Send> Scanner<T> {
+    /// Scans each line of `input` in parallel, passing the results to `f`.
+    ///
+    /// `f` is called on the current thread with the line number (starting
+    /// from 1) and the result of [`scan`](Self::scan) for every line, in the
+    /// order given by `options`. Lines are split as by
+    /// [`scan_lines`](Self::scan_lines). The errors of lines that fail are
+    /// also produced on the current thread, so they needn't be `Send`.
+    ///
+    /// If `f` or a scan panics, the other threads are stopped and the panic
+    /// is resumed on the current thread.
+    ///
+    /// Results are passed to a callback rather than returned as an iterator
+    /// because the scanning threads borrow the scanner and the input, so they
+    /// must finish before this function returns. An iterator would need its
+    /// own threads and an owned scanner, and would leave threads running when
+    /// it is dropped early. Only scanners without a context are supported,
+    /// since [`scan_with`](Self::scan_with) borrows its context mutably and a
+    /// context couldn't be shared between the threads.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, ParallelOptions};
+    /// let scanner = scanner!("{}", u32);
+    /// let mut sum = 0;
+    /// let mut bad_lines = vec![];
+    /// scanner.par_scan_slice(b"1\n2\nx\n4\n", ParallelOptions::new().chunk_size(2), |line_number, result| {
+    ///     match result {
+    ///         Ok(value) => sum += value,
+    ///         Err(_) => bad_lines.push(line_number),
+    ///     }
+    /// });
+    /// assert_eq!(7, sum);
+    /// assert_eq!(vec![3], bad_lines);
+    /// ```
+    pub fn par_scan_slice<'a>(&self, input: &'a [u8], options: ParallelOptions, f: impl FnMut(usize, Result<T>)) {
+        let chunk_size = options.chunk_size;
+        let split = move |mut sender: ChunkSender<&'a [u8]>| {
+            let mut rest = input;
+            while !rest.is_empty() {
+                let end = match rest.get(chunk_size..) {
+                    Some(tail) => tail.iter().position(|&b| b == b'\n').map_or(rest.len(), |pos| chunk_size + pos + 1),
+                    None => rest.len(),
+                };
+                let (data, tail) = rest.split_at(end);
+                rest = tail;
+                if !sender.send(data) {
+                    break;
+                }
+            }
+            Ok(())
+        };
+        // Splitting a slice cannot fail.
+        let _ = self.par_scan_chunks(split, options, f);
+    }
+
+    /// Reads lines from `reader` and scans them in parallel, passing the
+    /// results to `f`.
+    ///
+    /// The reader is read on a separate thread, one chunk at a time, so the
+    /// whole input never needs to be in memory. Otherwise, this behaves like
+    /// [`par_scan_slice`](Self::par_scan_slice). If reading fails, the results
+    /// of the chunks that were read are delivered, then the error is returned.
+    pub fn par_scan_reader(&self, reader: impl Read + Send, options: ParallelOptions, f: impl FnMut(usize, Result<T>)) -> IoResult<()> {
+        let chunk_size = options.chunk_size;
+        let max_line_len = self.limits().max_line_len;
+        let split = move |mut sender: ChunkSender<Vec<u8>>| {
+            let mut reader = BufReader::new(reader);
+            let mut buf = vec![];
+            loop {
+                let read = reader.by_ref().take(chunk_size as u64).read_to_end(&mut buf)?;
+                let end = if read == 0 {
+                    buf.len()
+                } else {
+                    match buf.iter().rposition(|&b| b == b'\n') {
+                        Some(pos) => pos + 1,
+                        None => match max_line_len {
+                            // Skip the rest of a line that is too long, keeping
+                            // enough of it to fail the limit when scanned.
+                            Some(max_len) if buf.len() > max_len.saturating_add(2) => {
+                                buf.truncate(max_len + 1);
+                                buf.push(b'\n');
+                                reader.skip_until(b'\n')?;
+                                buf.len()
+                            }
+                            // Keep reading until the end of the line.
+                            _ => continue,
+                        },
+                    }
+                };
+                if end == 0 {
+                    return Ok(());
+                }
+                let rest = buf.split_off(end);
+                if !sender.send(std::mem::replace(&mut buf, rest)) {
+                    return Ok(());
+                }
+            }
+        };
+        self.par_scan_chunks(split, options, f)
+    }
+
+    /// Opens the file at `path` and scans its lines in parallel, passing the
+    /// results to `f`.
+    ///
+    /// See [`par_scan_reader`](Self::par_scan_reader).
+    pub fn par_scan_file(&self, path: impl AsRef<Path>, options: ParallelOptions, f: impl FnMut(usize, Result<T>)) -> IoResult<()> {
+        self.par_scan_reader(File::open(path)?, options, f)
+    }
+
+    /// Scans the chunks produced by `split` on a pool of threads.
+    ///
+    /// Each chunk holds one of a fixed number of tickets until its results
+    /// have been delivered, so that only a bounded number of chunks are read
+    /// ahead or held back for ordering. If the current thread stops, the
+    /// tickets are dropped, which stops the splitter and then the workers.
+    fn par_scan_chunks<D: AsRef<[u8]> + Send>(
+        &self,
+        split: impl FnOnce(ChunkSender<D>) -> IoResult<()> + Send,
+        options: ParallelOptions,
+        mut f: impl FnMut(usize, Result<T>),
+    ) -> IoResult<()> {
+        let in_flight = options.threads * 2;
+        let (ticket_sender, ticket_receiver) = mpsc::sync_channel(in_flight);
+        for _ in 0..in_flight {
+            let _ = ticket_sender.send(());
+        }
+        let (chunk_sender, chunk_receiver) = mpsc::sync_channel(in_flight);
+        let chunk_receiver = Mutex::new(chunk_receiver);
+        let (result_sender, result_receiver) = mpsc::sync_channel(in_flight);
+        std::thread::scope(|scope| {
+            let tickets = ticket_sender;
+            let splitter = scope.spawn(move || split(ChunkSender {
+                sender: chunk_sender,
+                tickets: ticket_receiver,
+                index: 0,
+                next_line: 1,
+            }));
+            for _ in 0..options.threads {
+                let chunk_receiver = &chunk_receiver;
+                let result_sender = result_sender.clone();
+                scope.spawn(move || loop {
+                    let chunk = chunk_receiver.lock().unwrap_or_else(PoisonError::into_inner).recv();
+                    let Ok(chunk) = chunk else { break };
+                    // A panic is passed on to the current thread, which
+                    // resumes it once the other threads have stopped.
+                    let values = panic::catch_unwind(AssertUnwindSafe(|| self.scan_chunk(&chunk)));
+                    if result_sender.send(values.map(|values| (chunk, values))).is_err() {
+                        break;
+                    }
+                });
+            }
+            drop(result_sender);
+
+            let mut deliver = |chunk: Chunk<D>, values| {
+                self.deliver_chunk(&chunk, values, &mut f);
+                // The splitter may have finished, dropping its tickets.
+                let _ = tickets.send(());
+            };
+            let mut pending = BTreeMap::new();
+            let mut next_index = 0;
+            for result in result_receiver {
+                let (chunk, values) = result.unwrap_or_else(|panic| panic::resume_unwind(panic));
+                if !options.ordered {
+                    deliver(chunk, values);
+                    continue;
+                }
+                pending.insert(chunk.index, (chunk, values));
+                while let Some((chunk, values)) = pending.remove(&next_index) {
+                    deliver(chunk, values);
+                    next_index += 1;
+                }
+            }
+            splitter.join().unwrap_or_else(|panic| panic::resume_unwind(panic))
+        })
+    }
+
+    /// Scans each line of a chunk, returning the value of each line that
+    /// succeeded.
+    ///
+    /// Errors aren't returned, since they may not be `Send`.
+    fn scan_chunk<D: AsRef<[u8]>>(&self, chunk: &Chunk<D>) -> Vec<Option<T>> {
+        chunk.lines().map(|line| self.scan_line(line).ok()).collect()
+    }
+
+    /// Passes the results of a scanned chunk to `f`, with their line numbers.
+    /// The lines that failed are scanned again for their errors.
+    fn deliver_chunk<D: AsRef<[u8]>>(&self, chunk: &Chunk<D>, values: Vec<Option<T>>, f: &mut impl FnMut(usize, Result<T>)) {
+        for ((offset, line), value) in chunk.lines().enumerate().zip(values) {
+            let result = match value {
+                Some(value) => Ok(value),
+                None => self.scan_line(line),
+            };
+            f(chunk.first_line + offset, result);
+        }
+    }
+
+    /// Scans a line without its line ending, checking its length first.
+    fn scan_line(&self, line: &[u8]) -> Result<T> {
+        match self.limits().max_line_len {
+            Some(max_len) if line.len() > max_len => {
+                Err(ScanError::ScanLimitError(Limit::LineLength(max_len)).into())
+            }
+            _ => self.scan_slice(line),
+        }
+    }
+}
+
+/// A sequence of whole lines from the input.
+struct Chunk<D> {
+    index: usize,
+    first_line: usize,
+    data: D,
+}
+impl<D: AsRef<[u8]>> Chunk<D> {
+    /// Returns the lines of the chunk, without their line endings.
+    fn lines(&self) -> impl Iterator<Item = &[u8]> {
+        let data = self.data.as_ref();
+        // A chunk only ends without a newline at the end of the input.
+        let data = data.strip_suffix(b"\n").unwrap_or(data);
+        data.split(|&b| b == b'\n')
+            .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+    }
+}
+
+/// Numbers the chunks of the input and sends them to the scanning threads.
+struct ChunkSender<D> {
+    sender: mpsc::SyncSender<Chunk<D>>,
+    tickets: mpsc::Receiver<()>,
+    index: usize,
+    next_line: usize,
+}
+impl<D: AsRef<[u8]>> ChunkSender<D> {
+    /// Sends the next chunk once a ticket is available, returning `false` if
+    /// scanning has stopped.
+    fn send(&mut self, data: D) -> bool {
+        if self.tickets.recv().is_err() {
+            return false;
+        }
+        let lines = data.as_ref().iter().filter(|&&b| b == b'\n').count();
+        let chunk = Chunk { index: self.index, first_line: self.next_line, data };
+        self.index += 1;
+        self.next_line += lines;
+        self.sender.send(chunk).is_ok()
+    }
+}
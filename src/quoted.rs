@@ -0,0 +1,318 @@
+use crate::{Scan, DefaultScan, Print};
+use std::{error::Error as StdError, fmt, marker::PhantomData};
+
+/// A quoting convention understood by [`Quoted`].
+pub trait QuoteStyle {
+    /// Regex matching a complete quoted string, including its quotes.
+    const REGEX: &'static str;
+
+    /// Strips the quotes from `text` and decodes its escape sequences.
+    fn unquote(text: &str) -> Result<String, UnquoteError>;
+
+    /// Writes `text` to `out` with quotes, escaping it so that `unquote`
+    /// returns it unchanged.
+    ///
+    /// This is needed to print [`Quoted`] values. By default, it fails.
+    fn quote(text: &str, out: &mut dyn fmt::Write) -> fmt::Result {
+        let _ = (text, out);
+        Err(fmt::Error)
+    }
+}
+
+/// Strings surrounded by double quotes, like `"say \"hi\"\n"`.
+///
+/// The escape sequences `\n`, `\r`, `\t`, `\0`, `\\`, `\"`, `\'`, `\xNN`,
+/// `\uNNNN` and `\u{N...}` are decoded. `\xNN` denotes the code point
+/// `U+00NN`.
+pub struct DoubleQuotes;
+impl QuoteStyle for DoubleQuotes {
+    const REGEX: &'static str = r#""(?:[^"\\]|\\(?s:.))*""#;
+    fn unquote(text: &str) -> Result<String, UnquoteError> {
+        let body = strip_quotes(text, '"')?;
+        decode_escapes(body)
+    }
+    fn quote(text: &str, out: &mut dyn fmt::Write) -> fmt::Result {
+        encode_escapes(text, '"', out)
+    }
+}
+
+/// Strings surrounded by single quotes, like `'it\'s'`.
+///
+/// The same escape sequences as [`DoubleQuotes`] are decoded.
+pub struct SingleQuotes;
+impl QuoteStyle for SingleQuotes {
+    const REGEX: &'static str = r"'(?:[^'\\]|\\(?s:.))*'";
+    fn unquote(text: &str) -> Result<String, UnquoteError> {
+        let body = strip_quotes(text, '\'')?;
+        decode_escapes(body)
+    }
+    fn quote(text: &str, out: &mut dyn fmt::Write) -> fmt::Result {
+        encode_escapes(text, '\'', out)
+    }
+}
+
+/// A single word quoted according to the rules of a POSIX shell, like
+/// `"two words"` or `don\'t' 'panic`.
+///
+/// A word may mix unquoted, single-quoted and double-quoted parts. Outside of
+/// quotes, a backslash escapes any character. Inside double quotes, it only
+/// escapes `$`, `` ` ``, `"`, `\` and newlines. Inside single quotes, every
+/// character is taken literally.
+pub struct ShellQuotes;
+impl QuoteStyle for ShellQuotes {
+    const REGEX: &'static str = r#"(?:[^\s'"\\]|\\(?s:.)|'[^']*'|"(?:[^"\\]|\\(?s:.))*")+"#;
+    fn unquote(text: &str) -> Result<String, UnquoteError> {
+        let mut result = String::with_capacity(text.len());
+        let mut chars = text.chars();
+        while let Some(ch) = chars.next() {
+            match ch {
+                '\\' => result.push(chars.next().ok_or(UnquoteError::TrailingBackslash)?),
+                '\'' => loop {
+                    match chars.next().ok_or(UnquoteError::Unterminated)? {
+                        '\'' => break,
+                        ch => result.push(ch),
+                    }
+                },
+                '"' => loop {
+                    match chars.next().ok_or(UnquoteError::Unterminated)? {
+                        '"' => break,
+                        '\\' => match chars.next().ok_or(UnquoteError::Unterminated)? {
+                            '\n' => (),
+                            ch @ ('$' | '`' | '"' | '\\') => result.push(ch),
+                            ch => {
+                                result.push('\\');
+                                result.push(ch);
+                            }
+                        },
+                        ch => result.push(ch),
+                    }
+                },
+                ch if ch.is_whitespace() => return Err(UnquoteError::UnquotedWhitespace),
+                ch => result.push(ch),
+            }
+        }
+        Ok(result)
+    }
+    /// Words are always printed in single quotes, ending the quotes around
+    /// each `'` to escape it.
+    fn quote(text: &str, out: &mut dyn fmt::Write) -> fmt::Result {
+        out.write_char('\'')?;
+        for (idx, part) in text.split('\'').enumerate() {
+            if idx > 0 {
+                out.write_str(r"'\''")?;
+            }
+            out.write_str(part)?;
+        }
+        out.write_char('\'')
+    }
+}
+
+/// Implementation of [`Scan`](crate::Scan) and [`DefaultScan`](crate::DefaultScan)
+/// for `T`, reading it from inside a quoted string.
+///
+/// The quoting convention is chosen by `Q`, which defaults to [`DoubleQuotes`].
+/// The quotes are removed and escape sequences are decoded before the contents
+/// are passed on to `T`'s `scan`. The default regex matches a whole quoted
+/// string, regardless of `T`'s own default.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanln_from, Quoted, ShellQuotes, Error};
+/// let mut input = r#""a \"b\"\tc" -> 'x y'z"#.as_bytes();
+/// let (lhs, rhs) = scanln_from!(&mut input, "{} -> {}", Quoted<String>, Quoted<String, ShellQuotes>)?;
+/// assert_eq!("a \"b\"\tc", lhs);
+/// assert_eq!("x yz", rhs);
+/// # Ok::<(), Error>(())
+/// ```
+pub struct Quoted<T, Q = DoubleQuotes> { _phantom: PhantomData<(T, Q)> }
+impl<T: Scan, Q: QuoteStyle> Scan for Quoted<T, Q> where T::Error: 'static {
+    type Output = T::Output;
+    type Error = QuotedError<T::Error>;
+    fn scan(text: &str) -> Result<Self::Output, Self::Error> {
+        let unquoted = Q::unquote(text).map_err(QuotedError::Unquote)?;
+        T::scan(&unquoted).map_err(QuotedError::Inner)
+    }
+}
+impl<T: Scan, Q: QuoteStyle> DefaultScan for Quoted<T, Q> where T::Error: 'static {
+    const DEFAULT_REGEX: &'static str = Q::REGEX;
+}
+impl<T: Print, Q: QuoteStyle> Print for Quoted<T, Q> where T::Error: 'static {
+    fn print(value: &T::Output, out: &mut dyn fmt::Write) -> fmt::Result {
+        let mut text = String::new();
+        T::print(value, &mut text)?;
+        Q::quote(&text, out)
+    }
+}
+
+/// Error returned when scanning a [`Quoted`] value fails.
+#[derive(Debug)]
+pub enum QuotedError<E> {
+    /// The quotes or escape sequences were malformed.
+    Unquote(UnquoteError),
+    /// The unquoted contents could not be scanned.
+    Inner(E),
+}
+impl<E: StdError> std::fmt::Display for QuotedError<E> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::Unquote(error) => error.fmt(f),
+            Self::Inner(error) => write!(f, "{}", error),
+        }
+    }
+}
+impl<E: StdError + 'static> StdError for QuotedError<E> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Self::Unquote(error) => Some(error),
+            Self::Inner(error) => Some(error),
+        }
+    }
+}
+
+/// Error type indicating a malformed quoted string.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum UnquoteError {
+    /// The string did not start and end with the expected quote.
+    MissingQuotes,
+    /// A quoted section was never closed.
+    Unterminated,
+    /// The string ended with an unescaped backslash.
+    TrailingBackslash,
+    /// An unquoted, unescaped space appeared in a shell word.
+    UnquotedWhitespace,
+    /// An escape sequence was not recognized or encoded an invalid character.
+    InvalidEscape(String),
+}
+impl std::fmt::Display for UnquoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Self::MissingQuotes => write!(f, "string is not surrounded by quotes"),
+            Self::Unterminated => write!(f, "unterminated quoted string"),
+            Self::TrailingBackslash => write!(f, "string ends with a backslash"),
+            Self::UnquotedWhitespace => write!(f, "unquoted whitespace in shell word"),
+            Self::InvalidEscape(escape) => write!(f, "invalid escape sequence \"{}\"", escape),
+        }
+    }
+}
+impl StdError for UnquoteError {}
+
+/// Returns the contents of `text` between a leading and trailing `quote`.
+fn strip_quotes(text: &str, quote: char) -> Result<&str, UnquoteError> {
+    text.strip_prefix(quote)
+        .and_then(|text| text.strip_suffix(quote))
+        .ok_or(UnquoteError::MissingQuotes)
+}
+
+/// Writes `text` surrounded by `quote`, escaping backslashes, quotes and
+/// control characters with the sequences decoded by [`decode_escapes`].
+fn encode_escapes(text: &str, quote: char, out: &mut dyn fmt::Write) -> fmt::Result {
+    out.write_char(quote)?;
+    for ch in text.chars() {
+        match ch {
+            '\n' => out.write_str(r"\n")?,
+            '\r' => out.write_str(r"\r")?,
+            '\t' => out.write_str(r"\t")?,
+            '\0' => out.write_str(r"\0")?,
+            '\\' => out.write_str(r"\\")?,
+            ch if ch == quote => {
+                out.write_char('\\')?;
+                out.write_char(ch)?;
+            }
+            ch => out.write_char(ch)?,
+        }
+    }
+    out.write_char(quote)
+}
+
+/// Decodes the backslash escape sequences in `body`.
+fn decode_escapes(body: &str) -> Result<String, UnquoteError> {
+    let mut result = String::with_capacity(body.len());
+    let mut rest = body;
+    while let Some(idx) = rest.find('\\') {
+        result.push_str(&rest[..idx]);
+        let escape = &rest[idx..];
+        let (ch, len) = decode_escape(escape)?;
+        result.push(ch);
+        rest = &escape[len..];
+    }
+    result.push_str(rest);
+    Ok(result)
+}
+
+/// Decodes the escape sequence at the start of `escape`, returning the
+/// character and the length of the sequence.
+fn decode_escape(escape: &str) -> Result<(char, usize), UnquoteError> {
+    let invalid = |len: usize| {
+        let len = escape.char_indices().nth(len).map_or(escape.len(), |(idx, _)| idx);
+        UnquoteError::InvalidEscape(escape[..len].into())
+    };
+    // `from_str_radix` accepts a sign, so the digits are checked first.
+    let from_hex = |digits: &str| {
+        digits.bytes().all(|b| b.is_ascii_hexdigit())
+            .then(|| u32::from_str_radix(digits, 16).ok().and_then(char::from_u32))
+            .flatten()
+    };
+
+    let kind = escape[1..].chars().next().ok_or(UnquoteError::TrailingBackslash)?;
+    match kind {
+        'n' => Ok(('\n', 2)),
+        'r' => Ok(('\r', 2)),
+        't' => Ok(('\t', 2)),
+        '0' => Ok(('\0', 2)),
+        '\\' | '"' | '\'' => Ok((kind, 2)),
+        'x' => {
+            let digits = escape.get(2..4).ok_or_else(|| invalid(4))?;
+            let ch = from_hex(digits).ok_or_else(|| invalid(4))?;
+            Ok((ch, 4))
+        }
+        'u' if escape[2..].starts_with('{') => {
+            let end = escape.find('}').ok_or_else(|| invalid(3))?;
+            let digits = &escape[3..end];
+            let ch = (1..=6).contains(&digits.len())
+                .then(|| from_hex(digits))
+                .flatten()
+                .ok_or_else(|| invalid(end + 1))?;
+            Ok((ch, end + 1))
+        }
+        'u' => {
+            let digits = escape.get(2..6).ok_or_else(|| invalid(6))?;
+            let ch = from_hex(digits).ok_or_else(|| invalid(6))?;
+            Ok((ch, 6))
+        }
+        _ => Err(invalid(2)),
+    }
+}
+
+#[test]
+fn unquote_test() {
+    assert_eq!(Ok("a \"b\" \n c".into()), DoubleQuotes::unquote(r#""a \"b\" \n c""#));
+    assert_eq!(Ok("\u{e9}\u{1F600}é".into()), DoubleQuotes::unquote(r#""\xe9\u{1F600}é""#));
+    assert_eq!(Ok("it's".into()), SingleQuotes::unquote(r"'it\'s'"));
+    assert_eq!(Ok("don't panic".into()), ShellQuotes::unquote(r#"don\'t' 'pan"ic""#));
+    assert_eq!(Ok(r"\n$".into()), ShellQuotes::unquote(r#""\n\$""#));
+    assert_eq!(Err(UnquoteError::MissingQuotes), DoubleQuotes::unquote("abc"));
+    assert_eq!(Err(UnquoteError::InvalidEscape(r"\q".into())), DoubleQuotes::unquote(r#""\q""#));
+    assert_eq!(Err(UnquoteError::InvalidEscape(r"\u{110000}".into())), DoubleQuotes::unquote(r#""\u{110000}""#));
+    assert_eq!(Err(UnquoteError::InvalidEscape(r"\xg".into())), DoubleQuotes::unquote(r#""\xg""#));
+    assert_eq!(Err(UnquoteError::InvalidEscape(r"\u{+41}".into())), DoubleQuotes::unquote(r#""\u{+41}""#));
+    assert_eq!(Err(UnquoteError::InvalidEscape(r"\u+041".into())), DoubleQuotes::unquote(r#""\u+041""#));
+    assert_eq!(Err(UnquoteError::Unterminated), ShellQuotes::unquote("'abc"));
+}
+
+#[test]
+fn quote_test() {
+    fn quote<Q: QuoteStyle>(text: &str) -> String {
+        let mut out = String::new();
+        Q::quote(text, &mut out).unwrap();
+        out
+    }
+    assert_eq!(r#""a \"b\" \\ \n""#, quote::<DoubleQuotes>("a \"b\" \\ \n"));
+    assert_eq!(r"'it\'s'", quote::<SingleQuotes>("it's"));
+    assert_eq!(r"'don'\''t panic'", quote::<ShellQuotes>("don't panic"));
+    for text in ["", "a 'b' \"c\"", "\\\t\0\u{1F600}"] {
+        assert_eq!(Ok(text.into()), DoubleQuotes::unquote(&quote::<DoubleQuotes>(text)));
+        assert_eq!(Ok(text.into()), SingleQuotes::unquote(&quote::<SingleQuotes>(text)));
+        assert_eq!(Ok(text.into()), ShellQuotes::unquote(&quote::<ShellQuotes>(text)));
+    }
+}
@@ -1,5 +1,8 @@
-use std::io::{BufRead, Result as IoResult};
+use std::io::{BufRead, BufReader, Read, Result as IoResult};
+use std::str::Lines;
 use crate::{Scanner, Result};
+use crate::error::{Limit, ScanError};
+use crate::internal::{ReaderInput, ScanInput};
 
 /// Read values from a line of standard input.
 ///
@@ -29,13 +32,87 @@ macro_rules! scanln {
 ///
 /// See the [module-level documentation](crate) for a description of the
 /// argument syntax.
+///
+/// The scanner is only built the first time each invocation runs, so this
+/// macro is cheap to use in a loop. A regex other than a literal or `concat!`
+/// is evaluated every time, and a scanner is built and kept for each of its
+/// values, so a regex with many different values is better given to a
+/// scanner built with [`scanner!`](crate::scanner!).
+///
+/// ```
+/// # use rescan::scanln_from;
+/// let mut input = "abc\nABC\n".as_bytes();
+/// for pattern in ["[a-z]+", "[A-Z]+"] {
+///     assert_eq!(3, scanln_from!(&mut input, "{}", pattern as String)?.len());
+/// }
+/// # Ok::<(), rescan::Error>(())
+/// ```
 #[macro_export]
 macro_rules! scanln_from {
     ($r:expr, $($t:tt)+) => {{
-        match $crate::readers::read_line($r) {
-            Ok(line) => rescan::scanner!($($t)+).scan(&mut line.unwrap_or_default().as_slice()),
-            Err(err) => Err($crate::error::ScanError::from(err).into()),
+        $crate::readers::scan_line($r, $crate::scanner!(#[call_site] $($t)+))
+    }}
+}
+
+/// Read values from a string, a byte slice or a reader.
+///
+/// The input `$input` may be a `&str`, a `String`, a `&[u8]`, or a mutable
+/// reference to a [`BufRead`], which is advanced past the scanned text. Input
+/// after the end of the format is ignored.
+///
+/// See the [module-level documentation](crate) for a description of the
+/// argument syntax. As with [`scanln_from!`], the scanner is only built the
+/// first time each invocation runs, or for each value of a regex that isn't a
+/// literal.
+///
+/// # Panics
+///
+/// Panics if the input doesn't match the format. Use [`try_scan!`](crate::try_scan!) to handle
+/// such input instead.
+///
+/// # Example
+/// ```
+/// # use rescan::scan;
+/// let (name, age) = scan!("ada is 36", "{} is {}", String, u32);
+/// assert_eq!(("ada", 36), (name.as_str(), age));
+/// ```
+#[macro_export]
+macro_rules! scan {
+    ($input:expr, $($t:tt)+) => {
+        match $crate::try_scan_result!($input, $($t)+) {
+            Ok(value) => value,
+            Err(error) => panic!("failed to scan input: {}", error),
         }
+    }
+}
+
+/// Read values from a string, a byte slice or a reader, returning `None` if
+/// the input doesn't match the format.
+///
+/// See [`scan!`].
+///
+/// # Example
+/// ```
+/// # use rescan::try_scan;
+/// for line in ["x=1", "y", "z=3"] {
+///     if let Some((name, value)) = try_scan!(line, "{}={}", char, i32) {
+///         println!("{} is {}", name, value);
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! try_scan {
+    ($input:expr, $($t:tt)+) => {
+        $crate::try_scan_result!($input, $($t)+).ok()
+    }
+}
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! try_scan_result {
+    ($input:expr, $($t:tt)+) => {{
+        let input = $input;
+        $crate::_rescan_internal::ScanSource::scan_source(input, $crate::scanner!(#[call_site] $($t)+))
     }}
 }
 
@@ -51,18 +128,425 @@ impl<'a, Output> LineIter<'a, Output> {
     pub(crate) fn new(scanner: &'a Scanner<Output>, reader: &'a mut dyn BufRead) -> Self {
         Self { reader, scanner }
     }
+
+    /// Returns an iterator that skips lines that fail to scan, recording them
+    /// in a [`ScanReport`].
+    ///
+    /// The iterator yields only successfully scanned values. It stops early
+    /// once the failures exceed `budget`, or at the first I/O error. Either
+    /// way, the report (available from [`SkipErrors::report`]) records every
+    /// failing line and whether scanning was aborted.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, readers::ErrorBudget};
+    /// let scanner = scanner!("{}", u32);
+    /// let mut input = "1\nx\n3\n".as_bytes();
+    /// let mut values = scanner.scan_lines(&mut input).skip_errors(ErrorBudget::new().max_errors(5));
+    /// assert_eq!(vec![1, 3], values.by_ref().collect::<Vec<_>>());
+    ///
+    /// let report = values.into_report();
+    /// assert_eq!(1, report.failed());
+    /// assert_eq!(2, report.errors()[0].line_number);
+    /// assert_eq!("scanned 3 lines: 2 succeeded, 1 failed", report.to_string());
+    /// ```
+    pub fn skip_errors(self, budget: ErrorBudget) -> SkipErrors<'a, Output> {
+        SkipErrors {
+            scanner: self.scanner,
+            reader: self.reader,
+            budget,
+            report: ScanReport::default(),
+        }
+    }
 }
 impl<'a, Output> Iterator for LineIter<'a, Output> {
     type Item = Result<Output>;
     fn next(&mut self) -> Option<Self::Item> {
-        match read_line(self.reader).transpose() {
+        match read_line_limited(self.reader, self.scanner.limits().max_line_len).transpose() {
             Some(Ok(line)) => Some(self.scanner.scan(&mut line.as_slice())),
-            Some(Err(err)) => Some(Err(crate::error::ScanError::from(err).into())),
+            Some(Err(err)) => Some(Err(err.into())),
+            None => None,
+        }
+    }
+}
+
+/// An iterator that reads values from lines of a [`Read`], which it owns.
+///
+/// This struct is created by calling [`scan_read_lines`](crate::Scanner::scan_read_lines)
+/// with a `Read`.
+pub struct ReadLineIter<'a, Output, R> {
+    scanner: &'a Scanner<Output>,
+    reader: BufReader<R>,
+}
+impl<'a, Output, R: Read> ReadLineIter<'a, Output, R> {
+    pub(crate) fn new(scanner: &'a Scanner<Output>, reader: BufReader<R>) -> Self {
+        Self { scanner, reader }
+    }
+
+    /// Consumes the iterator, returning the underlying reader.
+    pub fn into_inner(self) -> BufReader<R> {
+        self.reader
+    }
+}
+impl<'a, Output, R: Read> Iterator for ReadLineIter<'a, Output, R> {
+    type Item = Result<Output>;
+    fn next(&mut self) -> Option<Self::Item> {
+        match read_line_limited(&mut self.reader, self.scanner.limits().max_line_len).transpose() {
+            Some(Ok(line)) => Some(self.scanner.scan_slice(&line)),
+            Some(Err(err)) => Some(Err(err.into())),
             None => None,
         }
     }
 }
 
+/// An iterator that reads values from the lines of a string.
+///
+/// This struct is created by calling [`scan_str_lines`](crate::Scanner::scan_str_lines)
+/// with a `&str`.
+pub struct StrLineIter<'a, Output> {
+    scanner: &'a Scanner<Output>,
+    lines: Lines<'a>,
+}
+impl<'a, Output> StrLineIter<'a, Output> {
+    pub(crate) fn new(scanner: &'a Scanner<Output>, text: &'a str) -> Self {
+        Self { scanner, lines: text.lines() }
+    }
+}
+impl<'a, Output> Iterator for StrLineIter<'a, Output> {
+    type Item = Result<Output>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(check_line_len(line, self.scanner).and_then(|line| self.scanner.scan_str(line)))
+    }
+}
+
+/// An iterator that reads values from each item of an iterator of lines.
+///
+/// This struct is created by calling [`scan_each`](crate::Scanner::scan_each)
+/// with an `IntoIterator` of strings.
+pub struct EachIter<'a, Output, I> {
+    scanner: &'a Scanner<Output>,
+    lines: I,
+}
+impl<'a, Output, I> EachIter<'a, Output, I> {
+    pub(crate) fn new(scanner: &'a Scanner<Output>, lines: I) -> Self {
+        Self { scanner, lines }
+    }
+}
+impl<'a, Output, I> Iterator for EachIter<'a, Output, I>
+where
+    I: Iterator,
+    I::Item: AsRef<str>,
+{
+    type Item = Result<Output>;
+    fn next(&mut self) -> Option<Self::Item> {
+        let line = self.lines.next()?;
+        Some(check_line_len(line.as_ref(), self.scanner).and_then(|line| self.scanner.scan_str(line)))
+    }
+}
+
+/// A buffered reader that can look ahead without consuming its input.
+///
+/// Like [`BufReader`], `PeekReader` implements [`BufRead`], so it can be
+/// scanned as usual. In addition, [`Scanner::peek`](crate::Scanner::peek) and
+/// [`Scanner::matches`](crate::Scanner::matches) try a scan against its input
+/// without consuming it, reading more into its buffer as needed.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanner, readers::PeekReader};
+/// let numbers = scanner!("{} {}\n", u32, u32);
+/// let words = scanner!("{} {}\n", String, String);
+/// let mut reader = PeekReader::new("1 2\nthree four\n".as_bytes());
+/// let mut records = vec![];
+/// while !reader.fill_buf()?.is_empty() {
+///     if numbers.matches(&mut reader) {
+///         let (a, b) = numbers.scan(&mut reader)?;
+///         records.push(format!("{}", a + b));
+///     } else {
+///         let (a, b) = words.scan(&mut reader)?;
+///         records.push(a + &b);
+///     }
+/// }
+/// assert_eq!(vec!["3", "threefour"], records);
+/// # use std::io::BufRead;
+/// # Ok::<(), Box<dyn std::error::Error>>(())
+/// ```
+pub struct PeekReader<R> {
+    inner: R,
+    buf: Vec<u8>,
+    pos: usize,
+}
+impl<R: Read> PeekReader<R> {
+    const CHUNK_SIZE: usize = 8 * 1024;
+
+    /// Creates a new `PeekReader` reading from `inner`.
+    pub fn new(inner: R) -> Self {
+        Self { inner, buf: vec![], pos: 0 }
+    }
+
+    /// Returns the input that has been read from the underlying reader but not
+    /// yet consumed.
+    pub fn buffer(&self) -> &[u8] {
+        &self.buf[self.pos..]
+    }
+
+    /// Consumes this `PeekReader`, returning the underlying reader. Any
+    /// buffered input is lost.
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+
+    /// Reads more input into the buffer, keeping the unconsumed input.
+    /// Returns the number of bytes read, which is `0` at the end of the input.
+    fn fill_more(&mut self) -> IoResult<usize> {
+        if self.pos == self.buf.len() {
+            self.buf.clear();
+            self.pos = 0;
+        }
+        let len = self.buf.len();
+        self.buf.resize(len + Self::CHUNK_SIZE, 0);
+        let read = self.inner.read(&mut self.buf[len..]);
+        self.buf.truncate(len + *read.as_ref().unwrap_or(&0));
+        read
+    }
+}
+impl<R: Read> Read for PeekReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let len = self.fill_buf()?.read(buf)?;
+        self.consume(len);
+        Ok(len)
+    }
+}
+impl<R: Read> BufRead for PeekReader<R> {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.pos == self.buf.len() {
+            self.fill_more()?;
+        }
+        Ok(self.buffer())
+    }
+    fn consume(&mut self, amt: usize) {
+        self.pos = (self.pos + amt).min(self.buf.len());
+    }
+}
+
+/// A [`ScanInput`] that reads from a [`PeekReader`] without consuming its
+/// input.
+pub(crate) struct Lookahead<'a, R> {
+    reader: &'a mut PeekReader<R>,
+    cursor: usize,
+}
+impl<'a, R: Read> Lookahead<'a, R> {
+    pub(crate) fn new(reader: &'a mut PeekReader<R>) -> Self {
+        let cursor = reader.pos;
+        Self { reader, cursor }
+    }
+}
+impl<R: Read> Read for Lookahead<'_, R> {
+    fn read(&mut self, buf: &mut [u8]) -> IoResult<usize> {
+        let len = self.fill_buf()?.read(buf)?;
+        self.consume(len);
+        Ok(len)
+    }
+}
+impl<R: Read> BufRead for Lookahead<'_, R> {
+    fn fill_buf(&mut self) -> IoResult<&[u8]> {
+        if self.cursor == self.reader.buf.len() {
+            // Appending to the buffer keeps everything before the cursor,
+            // unless nothing there is unconsumed.
+            let start = self.reader.pos;
+            self.reader.fill_more()?;
+            self.cursor -= start - self.reader.pos;
+        }
+        Ok(&self.reader.buf[self.cursor..])
+    }
+    fn consume(&mut self, amt: usize) {
+        self.cursor = (self.cursor + amt).min(self.reader.buf.len());
+    }
+}
+impl<R: Read> ScanInput for Lookahead<'_, R> {
+    fn fill_str(&mut self) -> std::result::Result<&str, ScanError> {
+        crate::internal::try_read_str(self)
+    }
+}
+
+/// Limits on the number of failing lines tolerated by [`SkipErrors`].
+///
+/// By default, the budget is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ErrorBudget {
+    max_errors: Option<usize>,
+    max_failure_rate: Option<(f64, usize)>,
+}
+impl ErrorBudget {
+    /// Creates an unlimited budget.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Aborts scanning once more than `max_errors` lines have failed.
+    pub fn max_errors(mut self, max_errors: usize) -> Self {
+        self.max_errors = Some(max_errors);
+        self
+    }
+
+    /// Aborts scanning once the fraction of failing lines exceeds `rate`
+    /// (between `0.0` and `1.0`).
+    ///
+    /// The rate is checked after every line once at least `min_lines` lines
+    /// have been read, so that a bad line near the start doesn't abort the
+    /// scan on its own. A line that scans successfully is still returned
+    /// when it brings the total to `min_lines` and the rate is exceeded.
+    pub fn max_failure_rate(mut self, rate: f64, min_lines: usize) -> Self {
+        self.max_failure_rate = Some((rate, min_lines));
+        self
+    }
+
+    /// Returns `true` if the failures in `report` exceed this budget.
+    fn is_exceeded(&self, report: &ScanReport) -> bool {
+        let too_many = self.max_errors.is_some_and(|max| report.failed() > max);
+        let too_frequent = self.max_failure_rate.is_some_and(|(rate, min_lines)| {
+            report.lines() >= min_lines && report.failure_rate() > rate
+        });
+        too_many || too_frequent
+    }
+}
+
+/// A line that failed to scan, as recorded in a [`ScanReport`].
+#[derive(Debug)]
+pub struct LineError {
+    /// The line number, starting from 1.
+    pub line_number: usize,
+    /// The contents of the line, without its line ending.
+    pub line: Vec<u8>,
+    /// The error returned when scanning the line.
+    pub error: crate::Error,
+}
+
+/// A summary of the lines read by [`SkipErrors`].
+#[derive(Debug, Default)]
+pub struct ScanReport {
+    lines: usize,
+    errors: Vec<LineError>,
+    aborted: bool,
+}
+impl ScanReport {
+    /// Returns the number of lines read.
+    pub fn lines(&self) -> usize {
+        self.lines
+    }
+
+    /// Returns the number of lines that were scanned successfully.
+    pub fn succeeded(&self) -> usize {
+        self.lines - self.errors.len()
+    }
+
+    /// Returns the number of lines that failed to scan.
+    pub fn failed(&self) -> usize {
+        self.errors.len()
+    }
+
+    /// Returns the fraction of lines that failed to scan, or `0.0` if no
+    /// lines were read.
+    pub fn failure_rate(&self) -> f64 {
+        if self.lines == 0 {
+            0.0
+        } else {
+            self.errors.len() as f64 / self.lines as f64
+        }
+    }
+
+    /// Returns `true` if scanning stopped before the end of the input, either
+    /// because the error budget was exceeded or because of an I/O error.
+    pub fn is_aborted(&self) -> bool {
+        self.aborted
+    }
+
+    /// Returns the lines that failed to scan, in order.
+    pub fn errors(&self) -> &[LineError] {
+        &self.errors
+    }
+
+    /// Consumes the report, returning the lines that failed to scan.
+    pub fn into_errors(self) -> Vec<LineError> {
+        self.errors
+    }
+}
+impl std::fmt::Display for ScanReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "scanned {} lines: {} succeeded, {} failed", self.lines, self.succeeded(), self.failed())?;
+        if self.aborted {
+            write!(f, " (aborted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// An iterator that reads values from lines of a [`BufRead`], skipping lines
+/// that fail to scan.
+///
+/// This struct is created by calling [`skip_errors`](LineIter::skip_errors)
+/// on a [`LineIter`].
+pub struct SkipErrors<'a, Output> {
+    scanner: &'a Scanner<Output>,
+    reader: &'a mut dyn BufRead,
+    budget: ErrorBudget,
+    report: ScanReport,
+}
+impl<'a, Output> SkipErrors<'a, Output> {
+    /// Returns the report of the lines read so far.
+    pub fn report(&self) -> &ScanReport {
+        &self.report
+    }
+
+    /// Consumes the iterator, returning the report of the lines read.
+    pub fn into_report(self) -> ScanReport {
+        self.report
+    }
+}
+impl<'a, Output> Iterator for SkipErrors<'a, Output> {
+    type Item = Output;
+    fn next(&mut self) -> Option<Self::Item> {
+        while !self.report.aborted {
+            let line = match read_line_limited(self.reader, self.scanner.limits().max_line_len) {
+                Ok(Some(line)) => line,
+                Ok(None) => return None,
+                Err(err) => {
+                    // A line that is too long has been skipped, but any other
+                    // error leaves the reader in an unknown state.
+                    let aborted = !matches!(err, ScanError::ScanLimitError(_));
+                    self.report.lines += 1;
+                    self.report.errors.push(LineError {
+                        line_number: self.report.lines,
+                        line: vec![],
+                        error: err.into(),
+                    });
+                    self.report.aborted = aborted || self.budget.is_exceeded(&self.report);
+                    continue;
+                }
+            };
+            self.report.lines += 1;
+            match self.scanner.scan(&mut line.as_slice()) {
+                Ok(value) => {
+                    // Earlier failures count towards the rate once enough
+                    // lines have been read, even if this line succeeded.
+                    self.report.aborted = self.budget.is_exceeded(&self.report);
+                    return Some(value);
+                }
+                Err(error) => {
+                    self.report.errors.push(LineError {
+                        line_number: self.report.lines,
+                        line,
+                        error,
+                    });
+                    self.report.aborted = self.budget.is_exceeded(&self.report);
+                }
+            }
+        }
+        None
+    }
+}
+
 /// An iterator that repeatedly reads values from a [`BufRead`].
 ///
 /// This struct is created by calling [`scan_multiple`](crate::Scanner::scan_multiple)
@@ -85,13 +569,56 @@ impl<'a, Output> Iterator for ScanIter<'a, Output> {
     type Item = Output;
     fn next(&mut self) -> Option<Self::Item> {
         if let (Some(sep), true) = (self.sep, self.expect_sep) {
-            crate::internal::match_literal(self.reader, sep).ok()?;
+            crate::internal::match_literal(&mut ReaderInput(self.reader), sep).ok()?;
         }
         self.expect_sep = true;
         self.scanner.scan(self.reader).ok()
     }
 }
 
+/// Read a single line of at most `max_len` bytes (not counting the line
+/// ending) from a [`BufRead`].
+///
+/// If the line is longer, the rest of it is skipped and a `ScanLimitError` is
+/// returned. Otherwise, this behaves like [`read_line`].
+pub(crate) fn read_line_limited(reader: &mut dyn BufRead, max_len: Option<usize>) -> Result<Option<Vec<u8>>, ScanError> {
+    let Some(max_len) = max_len else {
+        return Ok(read_line(reader)?);
+    };
+    // Leave room for a "\r\n" line ending.
+    let mut buf = vec![];
+    reader.take((max_len as u64).saturating_add(2)).read_until(b'\n', &mut buf)?;
+    let line_ending = [&b"\r\n"[..], b"\n"].into_iter().find(|ending| buf.ends_with(ending));
+    if buf.len() - line_ending.map_or(0, <[u8]>::len) > max_len {
+        if line_ending.is_none() {
+            reader.skip_until(b'\n')?;
+        }
+        return Err(ScanError::ScanLimitError(Limit::LineLength(max_len)));
+    }
+    let mut buf = buf.as_slice();
+    Ok(read_line(&mut buf)?)
+}
+
+/// Reads a single line from a [`BufRead`] and scans it, as `scanln_from!`
+/// does.
+///
+/// The line is read within the line length limit of `scanner`.
+#[doc(hidden)]
+pub fn scan_line<Output>(reader: &mut dyn BufRead, scanner: &Scanner<Output>) -> Result<Output> {
+    let line = read_line_limited(reader, scanner.limits().max_line_len)?;
+    scanner.scan(&mut line.unwrap_or_default().as_slice())
+}
+
+/// Returns `line` if it is within the line length limit of `scanner`.
+fn check_line_len<'l, Output>(line: &'l str, scanner: &Scanner<Output>) -> Result<&'l str> {
+    match scanner.limits().max_line_len {
+        Some(max_len) if line.len() > max_len => {
+            Err(ScanError::ScanLimitError(Limit::LineLength(max_len)).into())
+        }
+        _ => Ok(line),
+    }
+}
+
 /// Read a single line from a [`BufRead`].
 ///
 /// Reads bytes from `reader` until either the first newline character (`'\n'`)
@@ -102,9 +629,9 @@ pub fn read_line(reader: &mut dyn BufRead) -> IoResult<Option<Vec<u8>>> {
     let mut buf = vec![];
     reader.read_until(b'\n', &mut buf)?;
     if !buf.is_empty() {
-        if buf.ends_with(&[b'\n']) {
+        if buf.ends_with(b"\n") {
             buf.pop();
-            if buf.ends_with(&[b'\r']) {
+            if buf.ends_with(b"\r") {
                 buf.pop();
             }
         }
@@ -122,3 +649,13 @@ fn line_reader() {
     assert_eq!(Some(b"D"[..].to_vec()), read_line(&mut reader).unwrap());
     assert_eq!(None, read_line(&mut reader).unwrap().as_ref());
 }
+
+#[test]
+fn line_reader_limited() {
+    let mut reader = "abc\r\nabcd\nabcdefgh\r\nx".as_bytes();
+    assert_eq!(Some(b"abc".to_vec()), read_line_limited(&mut reader, Some(3)).unwrap());
+    assert!(matches!(read_line_limited(&mut reader, Some(3)), Err(ScanError::ScanLimitError(Limit::LineLength(3)))));
+    assert!(matches!(read_line_limited(&mut reader, Some(3)), Err(ScanError::ScanLimitError(_))));
+    assert_eq!(Some(b"x".to_vec()), read_line_limited(&mut reader, Some(3)).unwrap());
+    assert_eq!(None, read_line_limited(&mut reader, Some(3)).unwrap());
+}
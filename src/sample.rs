@@ -0,0 +1,265 @@
+use crate::internal::RegexFlags;
+use crate::{Scanner, Result};
+use regex_syntax::hir::{Class, Hir, HirKind};
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+/// A source of random inputs for [`Scanner::sample`].
+///
+/// A `Sampler` is a small pseudo-random generator, so the same seed always
+/// produces the same inputs. Unbounded repetitions in regexes, such as `*` and
+/// `+`, are repeated at most [`max_repeat`](Self::max_repeat) times more than
+/// their minimum.
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    seed: u64,
+    state: u64,
+    max_repeat: u32,
+    hirs: HashMap<(String, RegexFlags), Hir>,
+}
+impl Sampler {
+    /// Creates a sampler with the given seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            state: seed,
+            max_repeat: 8,
+            hirs: HashMap::new(),
+        }
+    }
+
+    /// Sets the number of extra repetitions allowed by an unbounded
+    /// repetition. The default is 8.
+    pub fn max_repeat(mut self, max_repeat: u32) -> Self {
+        self.max_repeat = max_repeat;
+        self
+    }
+
+    /// Returns the seed this sampler was created with.
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    /// Writes a random string matching the regex `pattern` to `out`.
+    #[doc(hidden)]
+    pub fn sample_regex(&mut self, pattern: &str, flags: RegexFlags, out: &mut String) {
+        let key = (pattern.to_string(), flags);
+        let hir = match self.hirs.remove(&key) {
+            Some(hir) => hir,
+            None => {
+                // An invalid regex fails when the scanner is used, so nothing
+                // is written for it.
+                let Ok(hir) = flags.parser().build().parse(pattern) else { return };
+                hir
+            }
+        };
+        self.sample_hir(&hir, out);
+        self.hirs.insert(key, hir);
+    }
+
+    /// Writes a random string matching `hir` to `out`.
+    ///
+    /// Look-around assertions, such as `^` and `\b`, are ignored, so the
+    /// string may not match if they are present.
+    fn sample_hir(&mut self, hir: &Hir, out: &mut String) {
+        match hir.kind() {
+            HirKind::Empty | HirKind::Look(_) => (),
+            HirKind::Literal(literal) => out.push_str(&String::from_utf8_lossy(&literal.0)),
+            HirKind::Class(Class::Unicode(class)) => {
+                let ranges = class.ranges().iter().map(|range| (u32::from(range.start()), u32::from(range.end())));
+                if let Some(ch) = self.pick_char(ranges) {
+                    out.push(ch);
+                }
+            }
+            HirKind::Class(Class::Bytes(class)) => {
+                // Only ASCII bytes can be written to a string.
+                let ranges = class.ranges().iter()
+                    .filter(|range| range.start().is_ascii())
+                    .map(|range| (u32::from(range.start()), u32::from(range.end().min(0x7F))));
+                if let Some(ch) = self.pick_char(ranges) {
+                    out.push(ch);
+                }
+            }
+            HirKind::Repetition(repetition) => {
+                let min = repetition.min;
+                let max = repetition.max.unwrap_or(min.saturating_add(self.max_repeat));
+                let count = min + self.up_to(u64::from(max - min)) as u32;
+                for _ in 0..count {
+                    self.sample_hir(&repetition.sub, out);
+                }
+            }
+            HirKind::Capture(capture) => self.sample_hir(&capture.sub, out),
+            HirKind::Concat(hirs) => hirs.iter().for_each(|hir| self.sample_hir(hir, out)),
+            HirKind::Alternation(hirs) => {
+                let idx = self.up_to(hirs.len() as u64 - 1) as usize;
+                self.sample_hir(&hirs[idx], out);
+            }
+        }
+    }
+
+    /// Returns a random character from the inclusive `ranges` of code points,
+    /// or `None` if there are none.
+    ///
+    /// Half of the time, the character is printable ASCII if possible, so
+    /// that samples from large classes such as `.` stay readable.
+    fn pick_char(&mut self, ranges: impl Iterator<Item = (u32, u32)>) -> Option<char> {
+        let ranges: Vec<_> = ranges.collect();
+        let printable: Vec<_> = ranges.iter()
+            .map(|&(start, end)| (start.max(0x20), end.min(0x7E)))
+            .filter(|(start, end)| start <= end)
+            .collect();
+        if !printable.is_empty() && self.up_to(1) == 0 {
+            // Weigh each range by its size, so that every character is as
+            // likely as any other.
+            let total: u32 = printable.iter().map(|(start, end)| end - start + 1).sum();
+            let mut offset = self.up_to(u64::from(total - 1)) as u32;
+            for (start, end) in printable {
+                let len = end - start + 1;
+                if offset < len {
+                    return char::from_u32(start + offset);
+                }
+                offset -= len;
+            }
+        }
+        let &(start, end) = ranges.get(self.up_to(ranges.len().checked_sub(1)? as u64) as usize)?;
+        let code = start + self.up_to(u64::from(end - start)) as u32;
+        // Ranges may span the surrogates, which aren't characters.
+        char::from_u32(code).or(char::from_u32(start))
+    }
+
+    /// Returns a random number in `0..=max`.
+    fn up_to(&mut self, max: u64) -> u64 {
+        match max.checked_add(1) {
+            Some(bound) => self.next_u64() % bound,
+            None => self.next_u64(),
+        }
+    }
+
+    /// Returns the next number from the SplitMix64 generator.
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+}
+
+/// A random input generated by [`Scanner::sample`], with the value it is
+/// expected to scan to.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sample<T> {
+    input: String,
+    value: Option<T>,
+}
+impl<T> Sample<T> {
+    /// Returns the generated input.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+
+    /// Returns the expected value, or `None` if some capture's text could not
+    /// be scanned by its type (such as an integer that is out of range).
+    pub fn value(&self) -> Option<&T> {
+        self.value.as_ref()
+    }
+
+    /// Consumes the sample, returning the input and the expected value.
+    pub fn into_parts(self) -> (String, Option<T>) {
+        (self.input, self.value)
+    }
+}
+
+impl<T> Scanner<T> {
+    /// Generates a random input for this scanner.
+    ///
+    /// The input is made of the literals of the format string and, for each
+    /// capture, a random string matching its regex. The expected value is
+    /// found by scanning the text of each capture with its type on its own.
+    /// An error is only returned if the scanner's regexes fail to compile.
+    ///
+    /// Scanning the whole input may still fail, for instance if a capture's
+    /// regex also matches the literal that follows it, which is what property
+    /// tests using [`assert_samples`](Self::assert_samples) are for.
+    ///
+    /// Nested values are generated with [`ScanFrom::sample_into`](crate::ScanFrom::sample_into).
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, Sampler, Error};
+    /// let scanner = scanner!("{}: {}", "[a-z]{1,5}" as String, "[0-9]{1,3}" as u16);
+    /// let sample = scanner.sample(&mut Sampler::new(42))?;
+    /// assert_eq!(sample.value(), Some(&scanner.scan_str(sample.input())?));
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn sample(&self, sampler: &mut Sampler) -> Result<Sample<T>> {
+        let regexes = self.regexes()?;
+        let mut input = String::new();
+        let value = self.sample_fn().and_then(|sample_fn| sample_fn(sampler, regexes, &mut input));
+        Ok(Sample { input, value })
+    }
+
+    /// Asserts that `count` random inputs scan to their expected values.
+    ///
+    /// Inputs without an expected value (see [`Sample::value`]) are skipped,
+    /// but at least half of them must have one, so that a regex accepting
+    /// mostly text that its type can't scan doesn't hide failures.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the offending input if an input fails to scan or scans to
+    /// a different value, if fewer than half of the inputs have an expected
+    /// value, or if the scanner's regexes fail to compile.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, Sampler};
+    /// let scanner = scanner!("({},{})", "-?[0-9]{1,4}" as i32, "-?[0-9]{1,4}" as i32);
+    /// scanner.assert_samples(&mut Sampler::new(1), 100);
+    /// ```
+    pub fn assert_samples(&self, sampler: &mut Sampler, count: usize)
+    where
+        T: PartialEq + Debug,
+    {
+        let mut checked = 0;
+        for idx in 0..count {
+            let sample = self.sample(sampler).unwrap_or_else(|error| panic!("{}", error));
+            let Some(expected) = &sample.value else { continue };
+            checked += 1;
+            match self.scan_str(&sample.input) {
+                Ok(value) => assert!(
+                    value == *expected,
+                    "sample {} of seed {} scanned to {:?} instead of {:?}: {:?}",
+                    idx, sampler.seed(), value, expected, sample.input,
+                ),
+                Err(error) => panic!(
+                    "sample {} of seed {} failed to scan: {}: {:?}",
+                    idx, sampler.seed(), error, sample.input,
+                ),
+            }
+        }
+        assert!(
+            checked * 2 >= count,
+            "only {} of {} samples of seed {} had an expected value; \
+             constrain the regexes to text their types can scan",
+            checked, count, sampler.seed(),
+        );
+    }
+}
+
+#[test]
+fn sample_regex_test() {
+    let regex = regex::Regex::new(r"^(?:[a-c]{2,4}|x+y?|\d\.\w*)$").unwrap();
+    let mut sampler = Sampler::new(7);
+    for _ in 0..200 {
+        let mut text = String::new();
+        sampler.sample_regex(r"[a-c]{2,4}|x+y?|\d\.\w*", RegexFlags::default(), &mut text);
+        assert!(regex.is_match(&text), "{:?}", text);
+    }
+
+    // Flags are applied when parsing.
+    let flags = RegexFlags { ignore_whitespace: Some(true), ..RegexFlags::default() };
+    let mut text = String::new();
+    sampler.sample_regex("a b # comment", flags, &mut text);
+    assert_eq!("ab", text);
+}
@@ -1,40 +1,335 @@
-use std::io::BufRead;
-use once_cell::unsync::Lazy;
+use std::fmt;
+use std::io::{BufRead, BufReader, Read};
+use std::path::Path;
+use once_cell::sync::Lazy;
 
-pub use regex::{Regex, Error as RegexError};
-pub use crate::Result;
-pub use crate::readers::{LineIter, ScanIter};
+pub use regex::Error as RegexError;
+use crate::error::ScanError;
+use crate::internal::{ScanInput, ReaderInput, SliceInput, LimitedInput, PartialSink, ScanRegex};
+use crate::Captures;
+pub use crate::{Result, ScanFrom, PartialScan};
+pub use crate::readers::{LineIter, ScanIter, ReadLineIter, StrLineIter, EachIter, PeekReader};
+use crate::readers::Lookahead;
+use crate::sample::Sampler;
 
 /// The type returned by the [`scanner!`] macro.
 ///
 /// To use this type, invoke [`scan`] or [`scan_lines`] with an instance of
 /// [`BufRead`].
 ///
+/// A `Scanner` is `Sync`, so it can be shared between threads or stored in a
+/// `static` (e.g. inside a [`once_cell::sync::Lazy`]). Its regexes are compiled
+/// on first use.
+///
+/// A scanner created with a `#[context(C)]` attribute depends on a context of
+/// type `C`, which is passed to [`scan_with`] and made available to capture
+/// types implementing [`ScanWith<C>`](crate::ScanWith).
+///
+/// A scanner created with a `#[printable]` attribute can also print values
+/// with [`format`], producing text that it scans back into the same value.
+///
 /// [`scanner!`]: crate::scanner!
 /// [`scan`]: Self::scan
 /// [`scan_lines`]: Self::scan_lines
-pub struct Scanner<T> {
-    lazy_regexes: Lazy<Result<Vec<Regex>, RegexError>>,
-    scan_fn: fn(&mut dyn BufRead, &[Regex]) -> Result<T>,
+/// [`scan_with`]: Self::scan_with
+/// [`format`]: Self::format
+pub struct Scanner<T, C: ?Sized = ()> {
+    lazy_regexes: Lazy<Result<Vec<ScanRegex>, RegexError>, RegexFn>,
+    scan_fn: ScanFn<T, C>,
+    print_fn: Option<fn(&T, &mut dyn fmt::Write) -> fmt::Result>,
+    sample_fn: Option<SampleFn<T>>,
+    limits: Limits,
 }
 
-impl<T> Scanner<T> {
+/// Compiles the regexes of a scanner. Scanners whose regexes depend on local
+/// variables own the patterns in the closure.
+type RegexFn = Box<dyn FnOnce() -> Result<Vec<ScanRegex>, RegexError> + Send>;
+
+/// Scans a value with the given regexes and context. If scanning fails and a
+/// sink is given, the captures scanned so far are passed to it.
+pub(crate) type ScanFn<T, C> = fn(&mut dyn ScanInput, &[ScanRegex], &mut C, Option<&mut dyn PartialSink<T>>) -> Result<T>;
+
+/// Generates a random input for a scanner with the given regexes, returning
+/// the value it should scan to.
+pub(crate) type SampleFn<T> = fn(&mut Sampler, &[ScanRegex], &mut String) -> Option<T>;
+
+/// Limits on the amount of input read by a [`Scanner`].
+///
+/// Input exceeding a limit fails to scan with a
+/// [`ScanLimitError`](crate::error::ScanError::ScanLimitError), instead of
+/// being buffered without bound. By default, there are no limits.
+///
+/// # Example
+/// ```
+/// # use rescan::{scanner, Limits};
+/// let scanner = scanner!("{}", ".*" as String).with_limits(Limits::new().max_capture_len(8));
+/// assert!(scanner.scan_str("short").is_ok());
+/// assert!(scanner.scan_str("much too long").is_err());
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Limits {
+    pub(crate) max_line_len: Option<usize>,
+    pub(crate) max_capture_len: Option<usize>,
+    pub(crate) max_scan_len: Option<usize>,
+}
+impl Limits {
+    /// Creates limits that allow any input.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Limits the length of each line read by the line iterators, such as
+    /// [`Scanner::scan_lines`], not counting the line ending.
+    ///
+    /// The rest of a line that is too long is skipped without being stored.
+    pub fn max_line_len(mut self, max_line_len: usize) -> Self {
+        self.max_line_len = Some(max_line_len);
+        self
+    }
+
+    /// Limits the length of the text matched by each capture.
+    pub fn max_capture_len(mut self, max_capture_len: usize) -> Self {
+        self.max_capture_len = Some(max_capture_len);
+        self
+    }
+
+    /// Limits the number of bytes matched by the literals and captures of a
+    /// single scan.
+    pub fn max_scan_len(mut self, max_scan_len: usize) -> Self {
+        self.max_scan_len = Some(max_scan_len);
+        self
+    }
+}
+
+impl<T, C: ?Sized> Scanner<T, C> {
     #[doc(hidden)]
-    pub fn new(regex_fn: fn() -> Result<Vec<Regex>, RegexError>, scan_fn: fn(&mut dyn BufRead, &[Regex]) -> Result<T>) -> Self {
+    pub fn new(
+        regex_fn: impl FnOnce() -> Result<Vec<ScanRegex>, RegexError> + Send + 'static,
+        scan_fn: ScanFn<T, C>,
+    ) -> Self {
         Self {
-            lazy_regexes: Lazy::new(regex_fn),
+            lazy_regexes: Lazy::new(Box::new(regex_fn)),
             scan_fn,
+            print_fn: None,
+            sample_fn: None,
+            limits: Limits::default(),
         }
     }
 
+    #[doc(hidden)]
+    pub fn with_printer(mut self, print_fn: fn(&T, &mut dyn fmt::Write) -> fmt::Result) -> Self {
+        self.print_fn = Some(print_fn);
+        self
+    }
+
+    #[doc(hidden)]
+    pub fn with_sampler(mut self, sample_fn: SampleFn<T>) -> Self {
+        self.sample_fn = Some(sample_fn);
+        self
+    }
+
+    /// Sets the limits on the input read by this scanner.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Returns the limits on the input read by this scanner.
+    pub fn limits(&self) -> &Limits {
+        &self.limits
+    }
+
+    /// Attempts to read values of type `T` from the reader, using the given
+    /// context.
+    ///
+    /// Captures whose types implement [`ScanWith<C>`](crate::ScanWith) may
+    /// read from and update `ctx` as they are scanned, as may nested scanners
+    /// that take the same context. Otherwise, this behaves like
+    /// [`scan`](Self::scan).
+    pub fn scan_with(&self, reader: &mut dyn BufRead, ctx: &mut C) -> Result<T> {
+        self.scan_input(&mut ReaderInput(reader), ctx)
+    }
+
+    /// Attempts to read values of type `T` from the reader, using the given
+    /// context, and keeps the captures that succeeded if scanning fails.
+    ///
+    /// See [`scan_partial`](Scanner::scan_partial).
+    pub fn scan_partial_with(&self, reader: &mut dyn BufRead, ctx: &mut C) -> Result<T, PartialScan<T::Partial>>
+    where
+        T: Captures,
+    {
+        let mut kept = KeepPartial(None);
+        self.run_scan(&mut ReaderInput(reader), ctx, Some(&mut kept))
+            .map_err(|error| match kept.0 {
+                Some((segment, captures)) => PartialScan::new(segment, error, captures),
+                None => PartialScan::new(0, error, T::from_whole(None)),
+            })
+    }
+
+    /// Returns `true` if this scanner was created with a `#[printable]`
+    /// attribute, so that it can print values.
+    pub fn is_printable(&self) -> bool {
+        self.print_fn.is_some()
+    }
+
+    /// Writes `value` to `out` in the format of this scanner.
+    ///
+    /// The literals of the format string are written as they are, and each
+    /// capture is written with the [`Print`](crate::Print) implementation of
+    /// its type. If the regex of every capture accepts its printed text, then
+    /// scanning the output returns a value equal to `value`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scanner is not printable (see
+    /// [`is_printable`](Self::is_printable)).
+    pub fn print(&self, value: &T, out: &mut dyn fmt::Write) -> fmt::Result {
+        let print_fn = self.print_fn.expect("scanner is not printable; add #[printable] to its format string");
+        print_fn(value, out)
+    }
+
+    /// Returns `value` printed in the format of this scanner.
+    ///
+    /// See [`print`](Self::print).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the scanner is not printable, or if printing a capture fails.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, Hex, Error};
+    /// let scanner = scanner!(#[printable] "{} -> #{}", u32, Hex<u8>);
+    /// let text = scanner.format(&(1, 255));
+    /// assert_eq!("1 -> #ff", text);
+    /// assert_eq!((1, 255), scanner.scan_str(&text)?);
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn format(&self, value: &T) -> String {
+        let mut text = String::new();
+        self.print(value, &mut text).expect("a capture failed to print");
+        text
+    }
+
+    /// Returns the function generating random inputs for this scanner, if it
+    /// doesn't take a context.
+    pub(crate) fn sample_fn(&self) -> Option<SampleFn<T>> {
+        self.sample_fn
+    }
+
+    /// Returns the compiled regexes of this scanner, compiling them on first
+    /// use.
+    pub(crate) fn regexes(&self) -> Result<&[ScanRegex]> {
+        Ok(self.lazy_regexes.as_ref()?)
+    }
+
+    pub(crate) fn scan_input(&self, input: &mut dyn ScanInput, ctx: &mut C) -> Result<T> {
+        self.run_scan(input, ctx, None)
+    }
+
+    fn run_scan(&self, input: &mut dyn ScanInput, ctx: &mut C, partial: Option<&mut dyn PartialSink<T>>) -> Result<T> {
+        let regexes = self.regexes()?;
+        if self.limits == Limits::default() {
+            (self.scan_fn)(input, regexes, ctx, partial)
+        } else {
+            (self.scan_fn)(&mut LimitedInput::new(input, self.limits), regexes, ctx, partial)
+        }
+    }
+}
+
+impl<T> Scanner<T> {
     /// Attempts to read values of type `T` from the reader.
     ///
     /// This function will fail if the contents of the reader do not match the
     /// format string used to create this `Scanner`. In this case, an `Err` is
     /// returned and the reader will have advanced by an unspecified amount.
     pub fn scan(&self, reader: &mut dyn BufRead) -> Result<T> {
-        let regexes = self.lazy_regexes.as_ref()?;
-        (self.scan_fn)(reader, regexes)
+        self.scan_with(reader, &mut ())
+    }
+
+    /// Attempts to read values of type `T` from the reader, keeping the
+    /// captures that succeeded if scanning fails.
+    ///
+    /// On failure, the returned [`PartialScan`] holds each capture scanned
+    /// before the failing segment, the index of that segment and the error.
+    /// This allows records that are almost well-formed to still be used. The
+    /// captures are kept in the output's [`Captures::Partial`] form: for
+    /// several captures, a tuple with an `Option` for each one.
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::scanner;
+    /// let scanner = scanner!("{} {} {}", u32, String, u32);
+    /// let partial = scanner.scan_partial(&mut "42 answer ?".as_bytes()).unwrap_err();
+    /// assert_eq!(4, partial.segment());
+    /// assert_eq!((Some(42), Some("answer".to_string()), None), partial.into_captures());
+    /// ```
+    pub fn scan_partial(&self, reader: &mut dyn BufRead) -> Result<T, PartialScan<T::Partial>>
+    where
+        T: Captures,
+    {
+        self.scan_partial_with(reader, &mut ())
+    }
+
+    /// Attempts to read values of type `T` from the start of a byte slice.
+    ///
+    /// This behaves like [`scan`](Self::scan), but is faster for input that
+    /// is already in memory: the slice is validated as UTF-8 only once,
+    /// rather than each time a segment of the format string is matched.
+    pub fn scan_slice(&self, input: &[u8]) -> Result<T> {
+        self.scan_input(&mut SliceInput::new(input), &mut ())
+    }
+
+    /// Reads the whole file at `path` into memory and attempts to read values
+    /// of type `T` from the start of it.
+    ///
+    /// If the scanner has a [`max_scan_len`](Limits::max_scan_len) limit, no
+    /// more of the file than that is read.
+    ///
+    /// See [`scan_slice`](Self::scan_slice).
+    pub fn scan_file(&self, path: impl AsRef<Path>) -> Result<T> {
+        // Reading one byte more than the scan limit is enough to detect that
+        // it is exceeded.
+        let max_len = self.limits.max_scan_len.map_or(u64::MAX, |max| max as u64 + 1);
+        let mut contents = vec![];
+        std::fs::File::open(path)
+            .and_then(|file| file.take(max_len).read_to_end(&mut contents))
+            .map_err(ScanError::from)?;
+        self.scan_slice(&contents)
+    }
+
+    /// Attempts to read values of type `T` from the start of a string.
+    ///
+    /// See [`scan_slice`](Self::scan_slice).
+    pub fn scan_str(&self, text: &str) -> Result<T> {
+        self.scan_input(&mut SliceInput::from_text(text), &mut ())
+    }
+
+    /// Attempts to read values of type `T` from an unbuffered reader.
+    ///
+    /// The reader is wrapped in a [`BufReader`], so any input it reads
+    /// beyond the end of the match is lost. To scan several values from the
+    /// same reader, wrap it in a `BufReader` and use [`scan`](Self::scan).
+    pub fn scan_read(&self, reader: impl Read) -> Result<T> {
+        self.scan(&mut BufReader::new(reader))
+    }
+
+    /// Attempts to read values of type `T` from the reader without consuming
+    /// any input.
+    ///
+    /// The scan is evaluated against the input buffered by `reader`, which
+    /// reads more as needed. Whether or not it succeeds, the next read from
+    /// `reader` starts at the same place as before.
+    pub fn peek<R: Read>(&self, reader: &mut PeekReader<R>) -> Result<T> {
+        self.scan_input(&mut Lookahead::new(reader), &mut ())
+    }
+
+    /// Returns `true` if values of type `T` could be read from the reader,
+    /// without consuming any input.
+    ///
+    /// See [`peek`](Self::peek).
+    pub fn matches<R: Read>(&self, reader: &mut PeekReader<R>) -> bool {
+        self.peek(reader).is_ok()
     }
 
     /// Returns an iterator that attempts to read values from lines of input.
@@ -50,6 +345,46 @@ impl<T> Scanner<T> {
         LineIter::new(self, reader)
     }
 
+    /// Returns an iterator that attempts to read values from lines of an
+    /// unbuffered reader.
+    ///
+    /// The reader is wrapped in a [`BufReader`], which is owned by the
+    /// iterator. Otherwise, this behaves like [`scan_lines`](Self::scan_lines).
+    pub fn scan_read_lines<R: Read>(&self, reader: R) -> ReadLineIter<'_, T, R> {
+        ReadLineIter::new(self, BufReader::new(reader))
+    }
+
+    /// Returns an iterator that attempts to read values from the lines of a
+    /// string.
+    ///
+    /// Lines are split as by [`str::lines`], which strips the same line
+    /// endings as [`scan_lines`](Self::scan_lines).
+    pub fn scan_str_lines<'a>(&'a self, text: &'a str) -> StrLineIter<'a, T> {
+        StrLineIter::new(self, text)
+    }
+
+    /// Returns an iterator that attempts to read values from each of a
+    /// sequence of lines, such as a `Vec<String>`.
+    ///
+    /// Each item is scanned as a whole, as by [`scan_str`](Self::scan_str).
+    ///
+    /// # Example
+    /// ```
+    /// # use rescan::{scanner, Error};
+    /// let lines = vec!["1 + 2".to_string(), "3 + 4".to_string()];
+    /// let scanner = scanner!("{} + {}", u32, u32);
+    /// let sums = scanner.scan_each(&lines).map(|pair| pair.map(|(a, b)| a + b));
+    /// assert_eq!(vec![3, 7], sums.collect::<Result<Vec<_>, Error>>()?);
+    /// # Ok::<(), Error>(())
+    /// ```
+    pub fn scan_each<I>(&self, lines: I) -> EachIter<'_, T, I::IntoIter>
+    where
+        I: IntoIterator,
+        I::Item: AsRef<str>,
+    {
+        EachIter::new(self, lines.into_iter())
+    }
+
     /// Returns an iterator that attempts to read values from lines of input.
     ///
     /// The iterator will repeatedly attempt to [`scan`](Self::scan) from `reader`.
@@ -67,3 +402,27 @@ impl<T> Scanner<T> {
         ScanIter::with_separator(self, reader, sep)
     }
 }
+
+impl<T> ScanFrom for Scanner<T> {
+    type Output = T;
+    fn scan_from(&self, reader: &mut dyn BufRead) -> Result<T> {
+        self.scan(reader)
+    }
+
+    fn sample_into(&self, sampler: &mut Sampler, out: &mut String) -> Option<T> {
+        let regexes = self.regexes().ok()?;
+        self.sample_fn()?(sampler, regexes, out)
+    }
+}
+
+/// Keeps the captures of a failed scan for [`Scanner::scan_partial_with`].
+struct KeepPartial<T: Captures>(Option<(usize, T::Partial)>);
+impl<T: Captures> PartialSink<T> for KeepPartial<T> {
+    fn keep(&mut self, segment: usize, captures: T::Partial) {
+        self.0 = Some((segment, captures));
+    }
+
+    fn keep_whole(&mut self, segment: usize, capture: Option<T>) {
+        self.0 = Some((segment, T::from_whole(capture)));
+    }
+}
@@ -66,3 +66,798 @@ fn line_iter() {
         ("few pirates".into(), "2 eyes".into()),
     ]);
 }
+
+
+#[test]
+fn grouped_numbers() {
+    use rescan::{Grouped, DotComma, SpaceComma, Decimal};
+
+    let reader = &mut "1,234,567.89 | 1.234.567,89 | -12 345,5 | 987".as_bytes();
+    let scanner = scanner!("{} | {} | {} | {}",
+        Grouped<f64>,
+        Grouped<Decimal, DotComma>,
+        Grouped<Decimal, SpaceComma>,
+        Grouped<u16>,
+    );
+
+    let (a, b, c, d) = scanner.scan(reader).unwrap();
+    assert_eq!(1_234_567.89, a);
+    assert_eq!(Decimal::new(123456789, 2), b);
+    assert_eq!(Decimal::new(-123455, 1), c);
+    assert_eq!(987, d);
+}
+
+
+#[test]
+fn quoted_strings() {
+    use rescan::{Quoted, SingleQuotes, ShellQuotes};
+
+    let reader = &mut r#"name="O'Brien, \"Pat\"\u{21}" age='42' cmd=ls\ -l' /tmp'"#.as_bytes();
+    let scanner = scanner!("name={} age={} cmd={}",
+        Quoted<String>,
+        Quoted<u32, SingleQuotes>,
+        Quoted<String, ShellQuotes>,
+    );
+
+    let (name, age, cmd) = scanner.scan(reader).unwrap();
+    assert_eq!("O'Brien, \"Pat\"!", name);
+    assert_eq!(42, age);
+    assert_eq!("ls -l /tmp", cmd);
+}
+
+
+#[test]
+fn regex_flags() {
+    let scanner = scanner!(#[flags = "i"] "[{}] message: {}",
+        #[flags = "i"] "error|warn" as String,
+        #[flags = "s"] ".+" as String,
+    );
+
+    let (level, message) = scanner.scan(&mut "[ERROR] Message: two\nlines".as_bytes()).unwrap();
+    assert_eq!("ERROR", level);
+    assert_eq!("two\nlines", message);
+    let (level, _) = scanner.scan(&mut "[Warn] MESSAGE: x".as_bytes()).unwrap();
+    assert_eq!("Warn", level);
+
+    let scanner = scanner!("{}", #[flags = "-u"] r"\w+" as String);
+    let word = scanner.scan(&mut "abcé".as_bytes()).unwrap();
+    assert_eq!("abc", word);
+}
+
+
+#[test]
+fn nested_scanners() {
+    use once_cell::sync::Lazy;
+    use rescan::{Error, Scanner, ScanFrom};
+
+    static POINT: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!("{},{}", i32, i32));
+
+    let scanner = scanner!("{} -> {} ({_})", use POINT, use POINT, r"\w+" as _);
+    let (from, to) = scanner.scan(&mut "1,2 -> -3,4 (ok)".as_bytes()).unwrap();
+    assert_eq!((1, 2), from);
+    assert_eq!((-3, 4), to);
+
+    match scanner.scan(&mut "1,2 -> 3;4 (ok)".as_bytes()) {
+        Err(Error::NestedError { position: Some(1), error }) => {
+            assert!(matches!(*error, Error::ScanError(_)));
+        }
+        _ => panic!("expected an error in the second capture"),
+    }
+
+    // Any type implementing `ScanFrom` can be nested.
+    struct Range;
+    impl ScanFrom for Range {
+        type Output = (u8, u8);
+        fn scan_from(&self, reader: &mut dyn std::io::BufRead) -> rescan::Result<(u8, u8)> {
+            scanner!("{}..{}", u8, u8).scan(reader)
+        }
+    }
+    let scanner = scanner!("[{}] {_}", use Range, use POINT);
+    assert_eq!((0, 10), scanner.scan(&mut "[0..10] 5,5".as_bytes()).unwrap());
+}
+
+
+#[test]
+fn scan_with_context() {
+    use once_cell::sync::Lazy;
+    use rescan::{Scanner, ScanWith};
+
+    /// A two-digit year, interpreted relative to a base century.
+    struct ShortYear;
+    impl ScanWith<u32> for ShortYear {
+        type Output = u32;
+        type Error = std::num::ParseIntError;
+        fn scan_with(text: &str, century: &mut u32) -> Result<u32, Self::Error> {
+            Ok(*century + text.parse::<u32>()?)
+        }
+    }
+
+    let scanner = scanner!(#[context(u32)] "{}/{}/{}", u8, u8, r"[0-9]{2}" as ShortYear);
+    let mut century = 1900;
+    assert_eq!((31, 12, 1999), scanner.scan_with(&mut "31/12/99".as_bytes(), &mut century).unwrap());
+    century = 2000;
+    assert_eq!((1, 1, 2000), scanner.scan_with(&mut "01/01/00".as_bytes(), &mut century).unwrap());
+
+    // Ordinary captures ignore the context.
+    let scanner = scanner!(#[context(u32)] "{}", u8);
+    assert_eq!(7, scanner.scan_with(&mut "7".as_bytes(), &mut century).unwrap());
+
+    // Nested scanners with the same context are given it, and others are
+    // scanned without it.
+    static DATE: Lazy<Scanner<(u8, u8, u32), u32>> = Lazy::new(|| {
+        scanner!(#[context(u32)] "{}/{}/{}", u8, u8, r"[0-9]{2}" as ShortYear)
+    });
+    static TIME: Lazy<Scanner<(u8, u8)>> = Lazy::new(|| scanner!("{}:{}", u8, u8));
+    let scanner = scanner!(#[context(u32)] "{} {}", use DATE, use TIME);
+    assert_eq!(((1, 2, 2003), (4, 5)), scanner.scan_with(&mut "01/02/03 04:05".as_bytes(), &mut century).unwrap());
+}
+
+
+#[test]
+fn scanln_in_loop() {
+    let reader = &mut "1 a\n2 b\n3 c\n".as_bytes();
+    let mut sum = 0;
+    let mut letters = String::new();
+    for _ in 0..3 {
+        let (num, letter) = rescan::scanln_from!(reader, "{} {}", u32, char).unwrap();
+        sum += num;
+        letters.push(letter);
+    }
+    assert_eq!(6, sum);
+    assert_eq!("abc", letters);
+}
+
+
+#[test]
+fn partial_scan() {
+    let scanner = scanner!("{1} {0} {2} [{3}]", u32, u32, char, u8);
+    let partial = scanner.scan_partial(&mut "1 2 x [300]".as_bytes()).unwrap_err();
+    assert_eq!(6, partial.segment());
+    assert!(matches!(partial.error(), rescan::Error::ParseError(_)));
+    assert_eq!((Some(2), Some(1), Some('x'), None), partial.into_captures());
+
+    let partial = scanner.scan_partial(&mut "1 ? x [4]".as_bytes()).unwrap_err();
+    assert_eq!(2, partial.segment());
+    assert!(matches!(partial.error(), rescan::Error::ScanError(_)));
+    assert_eq!((None, Some(1), None, None), *partial.captures());
+
+    assert_eq!((2, 1, 'x', 4), scanner.scan_partial(&mut "1 2 x [4]".as_bytes()).unwrap());
+
+    // A scanner without captures still reports the failing segment.
+    let scanner = scanner!("a{_}c", "b" as _);
+    assert_eq!(2, scanner.scan_partial(&mut "abd".as_bytes()).unwrap_err().segment());
+
+    // A single capture is kept as an `Option`.
+    let scanner = scanner!("{}!", u32);
+    let partial = scanner.scan_partial(&mut "5?".as_bytes()).unwrap_err();
+    assert_eq!((1, Some(5)), (partial.segment(), partial.into_captures()));
+}
+
+
+#[test]
+fn skip_errors() {
+    use rescan::readers::ErrorBudget;
+    const INPUT: &str = "a 1\nb x\nc 3\nd 400\ne 5\n";
+
+    let scanner = scanner!("{} {}", String, u8);
+
+    let input = &mut INPUT.as_bytes();
+    let mut values = scanner.scan_lines(input).skip_errors(ErrorBudget::new());
+    let names: Vec<_> = values.by_ref().map(|(name, _)| name).collect();
+    assert_eq!(vec!["a", "c", "e"], names);
+    let report = values.into_report();
+    assert_eq!((5, 3, 2), (report.lines(), report.succeeded(), report.failed()));
+    assert!(!report.is_aborted());
+    let errors = report.into_errors();
+    assert_eq!((2, &b"b x"[..]), (errors[0].line_number, &errors[0].line[..]));
+    assert_eq!((4, &b"d 400"[..]), (errors[1].line_number, &errors[1].line[..]));
+
+    // Too many errors.
+    let input = &mut INPUT.as_bytes();
+    let mut values = scanner.scan_lines(input).skip_errors(ErrorBudget::new().max_errors(1));
+    assert_eq!(2, values.by_ref().count());
+    assert!(values.report().is_aborted());
+    assert_eq!("scanned 4 lines: 2 succeeded, 2 failed (aborted)", values.report().to_string());
+
+    // Too frequent errors, once enough lines have been read.
+    let budget = ErrorBudget::new().max_failure_rate(0.3, 3);
+    let input = &mut INPUT.as_bytes();
+    let mut values = scanner.scan_lines(input).skip_errors(budget);
+    assert_eq!(2, values.by_ref().count());
+    assert_eq!(3, values.report().lines());
+    assert!(values.report().is_aborted());
+
+    // Failures before `min_lines` are checked once it is reached, even if the
+    // last line read succeeded.
+    let budget = ErrorBudget::new().max_failure_rate(0.4, 4);
+    let input = &mut "a x
+b x
+c 3
+d 4
+e 5
+".as_bytes();
+    let mut values = scanner.scan_lines(input).skip_errors(budget);
+    assert_eq!(2, values.by_ref().count());
+    assert_eq!(4, values.report().lines());
+    assert!(values.report().is_aborted());
+
+    // The rate is never checked if the input is shorter than `min_lines`.
+    let budget = ErrorBudget::new().max_failure_rate(0.4, 4);
+    let input = &mut "a x
+b x
+c 3
+".as_bytes();
+    let mut values = scanner.scan_lines(input).skip_errors(budget);
+    assert_eq!(1, values.by_ref().count());
+    assert!(!values.report().is_aborted());
+}
+
+
+#[test]
+fn parallel_scanning() {
+    use rescan::ParallelOptions;
+
+    let scanner = scanner!("{} {}", u32, u32);
+    let mut input = String::new();
+    for i in 0..1000 {
+        if i % 100 == 7 {
+            input.push_str("bad line\r\n");
+        } else {
+            input.push_str(&format!("{} {}\r\n", i, i * 2));
+        }
+    }
+    input.push_str("1000 2000");
+    let options = ParallelOptions::new().threads(4).chunk_size(64);
+
+    let mut ordered = vec![];
+    scanner.par_scan_slice(input.as_bytes(), options, |line_number, result| ordered.push((line_number, result.ok())));
+    assert_eq!(1001, ordered.len());
+    for (i, (line_number, result)) in ordered.iter().enumerate() {
+        assert_eq!(i + 1, *line_number);
+        let expected = (i % 100 != 7).then(|| (i as u32, i as u32 * 2));
+        assert_eq!(expected, *result);
+    }
+
+    let mut unordered = vec![];
+    scanner.par_scan_reader(input.as_bytes(), options.unordered(), |line_number, result| {
+        unordered.push((line_number, result.ok()));
+    }).unwrap();
+    unordered.sort_by_key(|(line_number, _)| *line_number);
+    assert_eq!(ordered, unordered);
+
+    let path = std::env::temp_dir().join(format!("rescan-parallel-{}.txt", std::process::id()));
+    std::fs::write(&path, &input).unwrap();
+    let mut errors = vec![];
+    scanner.par_scan_file(&path, options, |line_number, result| {
+        if result.is_err() {
+            errors.push(line_number);
+        }
+    }).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!((0..10).map(|i| i * 100 + 8).collect::<Vec<_>>(), errors);
+
+    // Lines longer than the limit and than several chunks are cut short while
+    // they are read, and still fail with the limit.
+    let scanner = scanner!("{}", u32).with_limits(rescan::Limits::new().max_line_len(8));
+    let long = "9".repeat(100);
+    let input = format!("1\n{}\r\n2\r\n{}\n3\n{}", long, long, long);
+    let mut results = vec![];
+    scanner.par_scan_reader(input.as_bytes(), options.chunk_size(4), |line_number, result| {
+        results.push((line_number, result.map_err(|error| error.to_string())));
+    }).unwrap();
+    let too_long = || Err("line is longer than 8 bytes".to_string());
+    assert_eq!(vec![(1, Ok(1)), (2, too_long()), (3, Ok(2)), (4, too_long()), (5, Ok(3)), (6, too_long())], results);
+}
+
+#[test]
+fn parallel_panics() {
+    use rescan::{ParallelOptions, Scan};
+    use std::panic::{self, AssertUnwindSafe};
+    use std::rc::Rc;
+
+    /// Panics on "boom", and fails with an error that isn't `Send`.
+    struct Fragile;
+    #[derive(Debug)]
+    struct FragileError(#[allow(dead_code)] Rc<str>);
+    impl std::fmt::Display for FragileError {
+        fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+            write!(f, "fragile")
+        }
+    }
+    impl std::error::Error for FragileError {}
+    impl Scan for Fragile {
+        type Output = u32;
+        type Error = FragileError;
+        fn scan(text: &str) -> Result<u32, FragileError> {
+            assert_ne!("boom", text, "exploded");
+            text.parse().map_err(|_| FragileError(text.into()))
+        }
+    }
+
+    let scanner = scanner!("{}", r"\w+" as Fragile);
+    let input = (0..1000).map(|i| format!("{}\n", i)).collect::<String>() + "x\n";
+    let options = ParallelOptions::new().threads(4).chunk_size(16);
+    let mut errors = vec![];
+    scanner.par_scan_slice(input.as_bytes(), options, |line_number, result| {
+        if let Err(error) = result {
+            errors.push((line_number, error.to_string()));
+        }
+    });
+    assert_eq!(vec![(1001, "fragile".to_string())], errors);
+
+    // A panic in the callback stops the scanning threads.
+    let panic = panic::catch_unwind(AssertUnwindSafe(|| {
+        scanner.par_scan_reader(input.as_bytes(), options, |line_number, _| assert!(line_number < 10, "stop")).unwrap();
+    }));
+    assert_eq!(Some(&"stop"), panic.unwrap_err().downcast_ref::<&str>());
+
+    // So does a panic while scanning, in any order.
+    let input = (0..1000).map(|i| if i == 500 { "boom\n".to_string() } else { format!("{}\n", i) }).collect::<String>();
+    for options in [options, options.unordered()] {
+        let panic = panic::catch_unwind(AssertUnwindSafe(|| {
+            scanner.par_scan_slice(input.as_bytes(), options, |_, _| {});
+        }));
+        let message = panic.unwrap_err().downcast::<String>().unwrap();
+        assert!(message.contains("exploded"), "{}", message);
+    }
+}
+
+
+#[test]
+fn scan_slice_and_file() {
+    use rescan::Scanner;
+    use once_cell::sync::Lazy;
+    static POINT: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!("({},{})", i32, i32));
+
+    let scanner = scanner!("{}: {} -> {}", String, use POINT, use POINT);
+    let input = "trajet: (1,2) -> (-3,4) ignored";
+    assert_eq!(("trajet".into(), (1, 2), (-3, 4)), scanner.scan_slice(input.as_bytes()).unwrap());
+    assert!(scanner.scan_slice(b"x: (1,\xff) -> (0,0)").is_err());
+
+    let scanner = scanner!("{}\n{}", #[flags = "s"] r"[^\n]*" as String, u32);
+    let path = std::env::temp_dir().join(format!("rescan-file-{}.txt", std::process::id()));
+    std::fs::write(&path, "première ligne\n42\n").unwrap();
+    let result = scanner.scan_file(&path);
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(("première ligne".into(), 42), result.unwrap());
+    assert!(scanner.scan_file(&path).is_err());
+}
+
+
+#[test]
+fn other_inputs() {
+    let scanner = scanner!("{}={}", String, i32);
+    assert_eq!(("a".into(), 1), scanner.scan_str("a=1").unwrap());
+    assert_eq!(("b".into(), -2), scanner.scan_read(&b"b=-2\nrest"[..]).unwrap());
+
+    let text = String::from("a=1\r\nb=x\n\nc=3");
+    let from_str: Vec<_> = scanner.scan_str_lines(&text).map(|result| result.ok()).collect();
+    let from_read: Vec<_> = scanner.scan_read_lines(text.as_bytes()).map(|result| result.ok()).collect();
+    let expected = vec![Some(("a".into(), 1)), None, None, Some(("c".into(), 3))];
+    assert_eq!(expected, from_str);
+    assert_eq!(expected, from_read);
+
+    let lines = vec!["x=10", "y=20"];
+    let values: Vec<_> = scanner.scan_each(lines).map(|result| result.unwrap().1).collect();
+    assert_eq!(vec![10, 20], values);
+}
+
+
+#[test]
+fn limits() {
+    use rescan::{Limits, error::{Limit, ScanError}};
+
+    let is_limit = |result: rescan::Result<_>, expected: Limit| match result {
+        Err(rescan::Error::ScanError(ScanError::ScanLimitError(limit))) => limit == expected,
+        _ => false,
+    };
+
+    let scanner = scanner!("{}: {}", r"\w+" as String, ".*" as String)
+        .with_limits(Limits::new().max_line_len(12).max_capture_len(6));
+    let input = "key: value\nkey: a very long value\nkey: longer\nk: v";
+    let results: Vec<_> = scanner.scan_lines(&mut input.as_bytes()).collect();
+    assert_eq!(4, results.len());
+    assert!(results[0].is_ok() && results[3].is_ok());
+    assert!(is_limit(results.into_iter().nth(1).unwrap().map(|_| ()), Limit::LineLength(12)));
+    assert!(is_limit(scanner.scan_str("key: longest").map(|_| ()), Limit::CaptureLength(6)));
+
+    let mut values = vec![];
+    scanner.par_scan_reader(input.as_bytes(), rescan::ParallelOptions::new().chunk_size(4), |line_number, result| {
+        values.push((line_number, result.is_ok()));
+    }).unwrap();
+    assert_eq!(vec![(1, true), (2, false), (3, true), (4, true)], values);
+
+    // The line read by `scanln_from!` is also limited, without being stored.
+    let reader = &mut input.as_bytes();
+    assert!(rescan::readers::scan_line(reader, &scanner).is_ok());
+    assert!(is_limit(rescan::readers::scan_line(reader, &scanner).map(|_| ()), Limit::LineLength(12)));
+    assert_eq!("longer", rescan::readers::scan_line(reader, &scanner).unwrap().1);
+
+    let scanner = scanner!("{} {} {}", u32, u32, u32).with_limits(Limits::new().max_scan_len(6));
+    assert!(scanner.scan_str("1 2 3").is_ok());
+    assert!(is_limit(scanner.scan_str("10 20 30").map(|_| ()), Limit::ScanLength(6)));
+
+    // The largest limit doesn't overflow when room is made for line endings.
+    let scanner = scanner!("{}", u32).with_limits(Limits::new().max_line_len(usize::MAX));
+    assert_eq!(vec![1, 2], scanner.scan_lines(&mut "1\r\n2".as_bytes()).collect::<rescan::Result<Vec<_>>>().unwrap());
+    let mut values = vec![];
+    scanner.par_scan_reader("1\n2\n".as_bytes(), rescan::ParallelOptions::new().chunk_size(1), |_, result| {
+        values.push(result.unwrap());
+    }).unwrap();
+    assert_eq!(vec![1, 2], values);
+}
+
+
+#[test]
+fn peek_and_matches() {
+    use rescan::readers::PeekReader;
+    use std::io::Read;
+
+    /// A reader that returns at most one line at a time, like a terminal.
+    struct Trickle<'a>(&'a [u8]);
+    impl Read for Trickle<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let line_len = self.0.iter().position(|&b| b == b'\n').map_or(self.0.len(), |pos| pos + 1);
+            let len = buf.len().min(line_len);
+            buf[..len].copy_from_slice(&self.0[..len]);
+            self.0 = &self.0[len..];
+            Ok(len)
+        }
+    }
+
+    let header = scanner!("# {}\n", ".*" as String);
+    let two_entries = scanner!("{0} = {_}\n{1} = {_}\n", r"\w+" as String, i64, r"\w+" as String, i64);
+    let entry = scanner!("{} = {}\n", r"\w+" as String, i64);
+    let mut reader = PeekReader::new(Trickle(b"# settings\nwidth = 80\nheight = 24\n"));
+
+    assert!(!entry.matches(&mut reader));
+    assert_eq!("settings", header.peek(&mut reader).unwrap());
+    assert_eq!("settings", header.scan(&mut reader).unwrap());
+    assert!(entry.matches(&mut reader));
+    assert_eq!(("width".into(), 80), entry.peek(&mut reader).unwrap());
+    // Looking ahead past the buffered line reads the next one.
+    assert_eq!(("width".into(), "height".into()), two_entries.peek(&mut reader).unwrap());
+    assert_eq!(("width".into(), 80), entry.scan(&mut reader).unwrap());
+    assert_eq!(("height".into(), 24), entry.scan(&mut reader).unwrap());
+    assert!(!entry.matches(&mut reader));
+}
+
+
+#[test]
+fn find_in_text() {
+    let scanner = scanner!("user={} id={}", r"\w+" as String, u32);
+    let log = "ada: hi user=bob id=3, user=eve id=x user=zoé id=12";
+    let found: Vec<_> = scanner.find_iter(log).unwrap()
+        .map(|found| (found.range(), found.into_value()))
+        .collect();
+    assert_eq!(vec![
+        (8..21, ("bob".into(), 3)),
+        (37..52, ("zoé".into(), 12)),
+    ], found);
+    assert!(scanner.find("nothing here").unwrap().is_none());
+
+    // Empty matches don't repeat.
+    let scanner = scanner!("{}", r"[0-9]*" as String);
+    let found: Vec<_> = scanner.find_iter("a1é").unwrap().map(|found| found.range()).collect();
+    assert_eq!(vec![0..0, 1..2, 2..2, 4..4], found);
+
+    let scanner = scanner!("{}", "(" as String);
+    assert!(scanner.find("text").is_err());
+
+    // Failed attempts don't search the rest of the text.
+    let scanner = scanner!("{}!", "[0-9]+" as u32);
+    let text = format!("{}7!", "x".repeat(200_000));
+    assert_eq!(200_000..200_002, scanner.find(&text).unwrap().unwrap().range());
+}
+
+
+#[test]
+fn print_round_trip() {
+    use rescan::{Scanner, Hex, Binary, Grouped, DotComma, Quoted, ShellQuotes};
+    use once_cell::sync::Lazy;
+
+    fn round_trip<T: PartialEq + std::fmt::Debug>(scanner: &Scanner<T>, value: T, expected: &str) {
+        let text = scanner.format(&value);
+        assert_eq!(expected, text);
+        assert_eq!(value, scanner.scan_str(&text).unwrap());
+    }
+
+    static POINT: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!(#[printable] "({},{})", i32, i32));
+
+    let scanner = scanner!(#[printable] "{} -> {}", use POINT, use POINT);
+    round_trip(&scanner, ((1, -2), (30, 4)), "(1,-2) -> (30,4)");
+
+    let scanner = scanner!(#[printable] "{1}={0}", r"\w+" as String, Quoted<String>);
+    round_trip(&scanner, ("say \"hi\"\n".into(), "greeting".into()), r#"greeting="say \"hi\"\n""#);
+
+    let scanner = scanner!(#[printable] "{} {} {} {}", Hex<i16>, Binary<u8>, Grouped<f64, DotComma>, Quoted<String, ShellQuotes>);
+    round_trip(&scanner, (-0x7f, 5, 1234.5, "it's".into()), r"-7f 101 1234,5 'it'\''s'");
+
+    let scanner = scanner!(#[printable] "[{}]", char);
+    round_trip(&scanner, 'x', "[x]");
+
+    let scanner = scanner!(#[printable] "literal only");
+    round_trip(&scanner, (), "literal only");
+
+    assert!(scanner.is_printable());
+    assert!(!scanner!("{}", u8).is_printable());
+}
+
+#[test]
+#[should_panic(expected = "nested scanner `PAIR` is not printable")]
+fn print_nested_unprintable() {
+    use rescan::Scanner;
+    use once_cell::sync::Lazy;
+
+    static PAIR: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!("{},{}", i32, i32));
+    let _ = scanner!(#[printable] "({})", use PAIR);
+}
+
+
+#[test]
+fn sample_inputs() {
+    use rescan::{Scanner, Sampler, Hex, Quoted, ScanFrom};
+    use once_cell::sync::Lazy;
+
+    static POINT: Lazy<Scanner<(i32, i32)>> = Lazy::new(|| scanner!("({},{})", "-?[0-9]{1,5}" as i32, "-?[0-9]{1,5}" as i32));
+
+    let mut sampler = Sampler::new(2024);
+    let scanner = scanner!("{} at {}: {} [{}]", r"[a-z][a-z0-9_]{0,6}" as String, use POINT, r#""[a-z ]{0,8}""# as Quoted<String>, "[0-9a-f]{1,2}" as Hex<u8>);
+    scanner.assert_samples(&mut sampler, 200);
+
+    let sample = scanner.sample(&mut sampler).unwrap();
+    assert!(sample.input().contains(" at ("));
+
+    // Samples are reproducible from their seed.
+    let first: Vec<_> = (0..5).map(|_| POINT.sample(&mut Sampler::new(9)).unwrap()).collect();
+    assert!(first.iter().all(|sample| sample == &first[0]));
+
+    // Captures that their type can't scan have no expected value.
+    let scanner = scanner!("{}", "[0-9]{4}" as u8);
+    assert_eq!(None, scanner.sample(&mut sampler).unwrap().value());
+
+    // Custom nested scanners produce no value by default.
+    struct Word;
+    impl ScanFrom for Word {
+        type Output = String;
+        fn scan_from(&self, reader: &mut dyn std::io::BufRead) -> rescan::Result<String> {
+            scanner!("{}", String).scan(reader)
+        }
+    }
+    let scanner = scanner!("<{}>", use Word);
+    assert_eq!(None, scanner.sample(&mut sampler).unwrap().value());
+}
+
+#[test]
+#[should_panic(expected = "failed to scan")]
+fn sample_ambiguous() {
+    // The first capture can swallow the separator.
+    let scanner = scanner!("{} {}", "[a-z ]{1,8}" as String, "[a-z]{1,8}" as String);
+    scanner.assert_samples(&mut rescan::Sampler::new(0), 100);
+}
+
+#[test]
+#[should_panic(expected = "had an expected value")]
+fn sample_unscannable() {
+    // Most integers of up to 8 digits are out of range.
+    let scanner = scanner!("{}", "[0-9]{1,8}" as u8);
+    scanner.assert_samples(&mut rescan::Sampler::new(0), 100);
+}
+
+
+#[test]
+fn inline_rules() {
+    use rescan::Hex;
+
+    let scanner = scanner!("{:u32} -> {name:String:/[a-z]+/}");
+    assert_eq!((12, "abc".into()), scanner.scan_str("12 -> abc").unwrap());
+
+    // Labelled rules can be reused, and mixed with arguments.
+    let scanner = scanner!("{word:String:/[a-z]{2}/i}-{:word}-{:std::num::NonZeroU8} {}", Hex<u16>);
+    let (first, second, number, hex) = scanner.scan_str("ab-Cd-7 ff").unwrap();
+    assert_eq!(("ab", "Cd", 7, 0xff), (first.as_str(), second.as_str(), number.get(), hex));
+
+    // An argument with the same name takes precedence over a type.
+    let scanner = scanner!("{:u8}", u8 = "[0-9]+" as i64);
+    assert_eq!(999_i64, scanner.scan_str("999").unwrap());
+
+    // Untyped inline regexes skip their match, and slashes are escaped.
+    let scanner = scanner!("{_:/[0-9]+\\//}{:String:/.*/}");
+    assert_eq!("rest", scanner.scan_str("12/rest").unwrap());
+}
+
+
+#[test]
+fn scan_into_places() {
+    use rescan::scan_into;
+
+    let (mut name, mut age, mut height) = (String::new(), 0_u32, 0.0_f64);
+    let mut input = "ada lovelace is 36 years old, 1.65m tall".as_bytes();
+    let count = scan_into!(&mut input, "{:/[a-z]+ [a-z]+/} is {} years old, {:/[0-9.]+/}m tall", &mut name, &mut age, &mut height);
+    assert_eq!(3, count.unwrap());
+    assert_eq!(("ada lovelace", 36, 1.65), (name.as_str(), age, height));
+
+    // Places are assigned until the input stops matching.
+    let (mut x, mut y) = (0_i32, 0_i32);
+    let reader = &mut "(3, oops)".as_bytes();
+    assert_eq!(1, scan_into!(reader, "({}, {})", &mut x, &mut y).unwrap());
+    assert_eq!((3, 0), (x, y));
+
+    // Places can be reused, and text skipped.
+    let mut values = [0_u8; 2];
+    let reader = &mut "7 -> 300 -> 9".as_bytes();
+    assert_eq!(2, scan_into!(reader, "{} -> {_:/[0-9]+/} -> {:0}", &mut values[1]).unwrap());
+    assert_eq!([0, 9], values);
+
+    // A reused place is only evaluated once.
+    let mut evaluated = 0;
+    let reader = &mut "4 5".as_bytes();
+    assert_eq!(2, scan_into!(reader, "{} {:0}", { evaluated += 1; &mut values[0] }).unwrap());
+    assert_eq!((1, [5, 9]), (evaluated, values));
+
+    // Errors other than mismatches are returned.
+    let mut word = String::new();
+    assert!(scan_into!(&mut "abc".as_bytes(), "{:/(/}", &mut word).is_err());
+}
+
+
+fn read_pair<T: rescan::DefaultScan<Output = T>>(reader: &mut dyn std::io::BufRead) -> rescan::Result<(T, T)>
+where
+    T::Error: 'static,
+{
+    scanner!("{} {}", T, T).scan(reader)
+}
+
+fn sum_lines<T>(mut reader: impl std::io::BufRead) -> T
+where
+    T: rescan::DefaultScan<Output = T> + std::iter::Sum + 'static,
+    T::Error: 'static,
+{
+    let mut values = Vec::new();
+    while let Ok(value) = rescan::scanln_from!(&mut reader, "{}", T) {
+        values.push(value);
+    }
+    values.into_iter().sum()
+}
+
+fn try_read<T: rescan::DefaultScan<Output = u32> + 'static>(text: &str) -> Option<u32>
+where
+    T::Error: 'static,
+{
+    rescan::try_scan!(text, "{}", T)
+}
+
+fn read_into<T: rescan::DefaultScan<Output = T> + Default>(text: &str) -> T
+where
+    T::Error: 'static,
+{
+    let mut value = T::default();
+    rescan::scan_into!(&mut text.as_bytes(), "{}", &mut value).unwrap();
+    value
+}
+
+#[test]
+fn generic_functions() {
+    assert_eq!((1, 2), read_pair::<u8>(&mut "1 2".as_bytes()).unwrap());
+    assert_eq!(('a', 'b'), read_pair::<char>(&mut "a b".as_bytes()).unwrap());
+    assert!(read_pair::<u8>(&mut "1 300".as_bytes()).is_err());
+
+    // Each type gets its own scanner at a call site within a generic function.
+    assert_eq!(6, sum_lines::<u32>("1\n2\n3\n".as_bytes()));
+    assert_eq!(3, sum_lines::<i64>("-1\n4\n".as_bytes()));
+    // Even when different types scan to the same output.
+    assert_eq!(Some(10), try_read::<u32>("10"));
+    assert_eq!(Some(10), try_read::<rescan::Hex<u32>>("a"));
+    assert_eq!(Some(16), try_read::<rescan::Hex<u32>>("10"));
+    assert_eq!(None, try_read::<u32>("a"));
+    assert_eq!(12, read_into::<u8>("12ab"));
+    assert_eq!("12ab", read_into::<String>("12ab"));
+}
+
+
+#[test]
+fn runtime_regexes() {
+    struct Config {
+        id_pattern: String,
+    }
+
+    let config = Config { id_pattern: "[A-Z]{2}[0-9]{3}".to_string() };
+    let scanner = scanner!("id={} name={}", config.id_pattern as String, "[a-z]+" as String);
+    assert_eq!(("AB123".to_string(), "ada".to_string()), scanner.scan_str("id=AB123 name=ada").unwrap());
+    assert!(scanner.scan_str("id=ab123 name=ada").is_err());
+
+    // The scanner owns its patterns, so it can outlive the locals they came from.
+    let scanner = {
+        let digits = 3;
+        let pattern = format!("[0-9]{{{}}}", digits);
+        scanner!("{}{}", pattern as u32, &pattern as u32)
+    };
+    assert_eq!((123, 456), scanner.scan_str("123456").unwrap());
+    scanner.assert_samples(&mut rescan::Sampler::new(3), 20);
+
+    // Invalid patterns fail when the scanner is first used.
+    let pattern = "(";
+    assert!(scanner!("{}", pattern as String).scan_str("x").is_err());
+
+    // Whether a regex is constant doesn't depend on the case of its name.
+    #[allow(non_upper_case_globals)]
+    const lower: &str = "[a-z]+";
+    assert_eq!("abc", scanner!("{}", lower as String).scan_str("abc1").unwrap());
+    assert_eq!("abc", rescan::scan!("abc1", "{}", lower as String));
+    #[allow(non_snake_case)]
+    for (UPPER, expected) in [("[0-9]", "1"), ("[0-9]{2}", "12")] {
+        assert_eq!(expected, scanner!("{}", UPPER as String).scan_str("123").unwrap());
+        assert_eq!(expected, rescan::scan!("123", "{}", UPPER as String));
+    }
+
+    // A call site keeps a scanner for each pattern.
+    let mut reader = "1\n23\n4\n".as_bytes();
+    let mut lines = vec![];
+    for pattern in ["[0-9]", "[0-9]{2}", "[0-9]"] {
+        lines.push(rescan::scanln_from!(&mut reader, "{}", pattern as u32).unwrap());
+    }
+    assert_eq!(vec![1, 23, 4], lines);
+}
+
+
+#[test]
+fn scan_macros() {
+    use rescan::{scan, try_scan};
+
+    assert_eq!((1, 2), scan!("1,2", "{},{}", u8, u8));
+    assert_eq!((1, 2), scan!(String::from("1,2"), "{},{}", u8, u8));
+    assert_eq!((1, 2), scan!(b"1,2 extra", "{},{}", u8, u8));
+    assert_eq!((1, 2), scan!(&b"1,2".to_vec(), "{},{}", u8, u8));
+
+    // A reader is advanced past the scanned text.
+    let mut reader = "1 2 3".as_bytes();
+    assert_eq!(1, scan!(&mut reader, "{} ", u8));
+    assert_eq!(2, scan!(&mut reader, "{} ", u8));
+    assert_eq!(b"3", reader);
+
+    let lines = ["a=1", "oops", "c=3"];
+    let mut values = vec![];
+    for line in lines {
+        values.extend(try_scan!(line, "{}={}", char, u32));
+    }
+    assert_eq!(vec![('a', 1), ('c', 3)], values);
+    assert_eq!(None, try_scan!(&lines[1].to_string(), "{}={}", char, u32));
+}
+
+#[test]
+#[should_panic(expected = "failed to scan input")]
+fn scan_macro_mismatch() {
+    rescan::scan!("x", "{}", u32);
+}
+
+
+#[rescan::format_string]
+const POINT: &str = "({}, {})";
+
+mod formats {
+    #[rescan::format_string]
+    pub const RECORD: &str = concat!("#{} ", include_str!("record.fmt"));
+}
+
+#[test]
+fn format_string_forms() {
+    use formats::RECORD;
+
+    assert_eq!((1, 2), scanner!(POINT, i32, i32).scan_str("(1, 2)").unwrap());
+    assert_eq!("(3, 4)", scanner!(#[printable] POINT, i32, i32).format(&(3, 4)));
+    assert_eq!("({}, {})", POINT);
+
+    let record = (7, "ada".to_string(), 36);
+    assert_eq!(record, scanner!(RECORD, u32, u8).scan_str("#7 name=ada age=36").unwrap());
+    assert_eq!(record, scanner!(formats::RECORD, u32, u8).scan_str("#7 name=ada age=36").unwrap());
+    assert_eq!(record, rescan::scan!("#7 name=ada age=36", RECORD, u32, u8));
+
+    let scanner = scanner!(concat!("{}", '-', 2, "{}"), u8, char);
+    assert_eq!((1, 'x'), scanner.scan_str("1-2x").unwrap());
+    let scanner = scanner!(include_str!("record.fmt"), u8);
+    assert_eq!(("ada".to_string(), 36), scanner.scan_str("name=ada age=36").unwrap());
+
+    let (mut x, mut y) = (0, 0);
+    assert_eq!(2, rescan::scan_into!(&mut "(5, 6)".as_bytes(), POINT, &mut x, &mut y).unwrap());
+    assert_eq!((5, 6), (x, y));
+}